@@ -0,0 +1,47 @@
+#[macro_use]
+extern crate criterion;
+extern crate trees;
+
+use criterion::{black_box, Criterion};
+use trees::arena::{Node, Storage, Tree};
+use trees::arena::{InterleavedStorage, SplitStorage};
+
+const NODE_COUNT: usize = 100_000;
+
+#[derive(Clone)]
+struct Payload {
+    key: u64,
+    _data: [u8; 248],
+}
+
+/// Builds a tree with a random shape, so the creation order does not match the traversal order.
+fn build<S: Storage<Payload, u32>>() -> (Tree<(), Payload, u32, S>, Node<()>) {
+    let mut t = Tree::new(());
+    let root = t.create_node(Payload { key: 0, _data: [0; 248] });
+    let mut nodes = vec![root];
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    for i in 1..NODE_COUNT {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        let parent = nodes[(seed >> 33) as usize % nodes.len()];
+        let n = parent.append_child_value(&mut t, Payload { key: i as u64, _data: [0; 248] });
+        nodes.push(n);
+    }
+    (t, root)
+}
+
+fn bench_storage<S: Storage<Payload, u32>>(c: &mut Criterion, name: &str) {
+    let (t, root) = build::<S>();
+    c.bench_function(&format!("{}/depth_first_search", name), |b| b.iter(|| black_box(root.depth_first_search(&t).count())));
+    c.bench_function(&format!("{}/depth_first_search_values", name), |b| {
+        b.iter(|| black_box(root.depth_first_search(&t).map(|(_, n)| n.value(&t).key).sum::<u64>()))
+    });
+}
+
+fn traversal(c: &mut Criterion) {
+    assert_eq!(std::mem::size_of::<Payload>(), 256);
+    bench_storage::<InterleavedStorage<Payload, u32>>(c, "interleaved");
+    bench_storage::<SplitStorage<Payload, u32>>(c, "split");
+}
+
+criterion_group!(benches, traversal);
+criterion_main!(benches);
@@ -4,15 +4,13 @@ use trees::arena::Tree;
 
 fn print_tree(t: &Tree<(), String>, title: &str) {
     println!("{}", title);
-    if let Some(root) = t.first_root_node() {
-        for (depth, node) in root.depth_first_search(t) {
-            for _ in 0..depth {
-                print!("  ");
-            }
-            println!("- {}", node.value(t));
+    for (depth, node) in t.depth_first_search() {
+        for _ in 0..depth {
+            print!("  ");
         }
+        println!("- {}", node.value(t));
     }
-    println!("");
+    println!();
 }
 
 fn main() {
@@ -22,7 +20,7 @@ fn main() {
     // Build the tree
     let root = {
         let t = &mut tree;
-        let root = t.create_node("Root".into());
+        let root = t.push_root_value("Root".into());
         let a = root.append_child_value(t, "Parent A".into());
         a.append_child_value(t, "Child A1".into());
         a.append_child_value(t, "Child A2".into());
@@ -87,7 +85,7 @@ fn main() {
 
         println!("=== Names of Parent A's Children using Standard Iterator ===");
         println!("  {:?}", &names);
-        println!("");
+        println!();
     }
 
     // Root method
@@ -109,7 +107,7 @@ fn main() {
         println!("Root node for {:?} => {:?}", a.value(t), a.root(t).value(t));
         println!("Root node for {:?} => {:?}", a1.value(t), a1.root(t).value(t));
 
-        println!("");
+        println!();
     }
 
     // Depth-first search with discard
@@ -172,4 +170,21 @@ fn main() {
 
         print_tree(t, "=== Insert Prev Sibling ===");
     }
+
+    // Multiple roots (a forest)
+    {
+        let mut tree = tree.clone();
+        let t = &mut tree;
+
+        root.insert_next_sibling_value(t, "Second Root".into());
+        let first = root.insert_prev_sibling_value(t, "First Root".into());
+        first.append_child_value(t, "Child F1".into());
+
+        let names: Vec<_> = t.roots().map(|n| n.value(t)).collect();
+        println!("=== Roots ===");
+        println!("  {:?}", &names);
+        println!();
+
+        print_tree(t, "=== Insert Root Siblings ===");
+    }
 }
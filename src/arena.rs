@@ -1,77 +1,477 @@
 use context_iter::ContextIterator;
+use dot::{self, DotOptions};
 use std;
+use std::borrow::Borrow;
+use std::num::{NonZeroU16, NonZeroU32, NonZeroUsize};
+use walk::Links;
+use {InsertPosition, InvariantReport, NodePath, RetainMode, ViolationKind};
 
+mod cursor;
+mod lca;
+mod node_ref;
+mod order;
+pub mod rewrite;
+mod storage;
+pub use self::cursor::{CursorMut, TreeCursor};
+pub use self::lca::LcaIndex;
+pub use self::node_ref::{Ancestors, Children, Descendants, NodeMut, NodeRef};
+pub use self::order::OrderIndex;
+pub use self::storage::{InterleavedStorage, SplitStorage, Storage};
+
+/// The integer type used to store node indexes (and therefore links) within a tree. Implemented
+/// for `u16`, `u32` (the default) and `usize`. Indexes are stored as non-zero values, so an
+/// `Option<Node>` is no larger than a `Node`.
+pub trait IndexType: Copy + Eq + std::hash::Hash + std::fmt::Debug + 'static {
+    /// Non-zero representation of an index (the index plus one).
+    type Repr: Copy + Eq + Ord + std::hash::Hash + std::fmt::Debug;
+
+    /// The maximum number of nodes that a tree using this index type can hold.
+    const MAX_NODES: usize;
+
+    /// Returns the representation of the index, or `None` if it exceeds the capacity limit.
+    fn to_repr(idx: usize) -> Option<Self::Repr>;
+    fn from_repr(repr: Self::Repr) -> usize;
+}
+
+macro_rules! impl_index_type {
+    ($ix:ty, $repr:ty) => {
+        impl IndexType for $ix {
+            type Repr = $repr;
+
+            const MAX_NODES: usize = <$ix>::MAX as usize;
+
+            fn to_repr(idx: usize) -> Option<$repr> {
+                if idx < Self::MAX_NODES {
+                    <$repr>::new((idx + 1) as $ix)
+                } else {
+                    None
+                }
+            }
+            fn from_repr(repr: $repr) -> usize { repr.get() as usize - 1 }
+        }
+    };
+}
+impl_index_type!(u16, NonZeroU16);
+impl_index_type!(u32, NonZeroU32);
+impl_index_type!(usize, NonZeroUsize);
+
+/// Returned when a node cannot be created because the tree already holds the maximum number of
+/// nodes supported by its index type.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct CapacityError {
+    max_nodes: usize,
+}
+impl CapacityError {
+    pub fn max_nodes(&self) -> usize { self.max_nodes }
+}
+impl std::fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "The tree has reached its capacity of {} nodes, use a larger index type", self.max_nodes)
+    }
+}
+impl std::error::Error for CapacityError {}
+
+/// An arena of nodes. The tree keeps an ordered list of top-level roots (see `push_root`), which
+/// are linked to each other as siblings, so the tree can hold a forest. Nodes that are neither a
+/// root nor underneath one are detached.
+///
+/// The index type `Ix` limits the number of nodes (removed nodes keep their slot). The default
+/// `u32` allows `u32::MAX` nodes and keeps each link at 4 bytes; use `usize` for larger trees, or
+/// `u16` for small trees (up to `u16::MAX` nodes) to shrink the links further. `create_node` panics
+/// once the limit is reached, while `try_create_node` returns a `CapacityError`.
 #[derive(Clone)]
-pub struct Tree<IdType: Copy + Eq, T> {
+pub struct Tree<IdType: Copy + Eq, T, Ix: IndexType = u32, S: Storage<T, Ix> = InterleavedStorage<T, Ix>> {
     id: IdType,
-    nodes: Vec<NodeData<T>>,
+    nodes: S,
+    first_root: NodeIdx<Ix>,
+    last_root: NodeIdx<Ix>,
+    _marker: std::marker::PhantomData<(T, Ix)>,
 }
 
-impl<IdType: Copy + Eq, T> Tree<IdType, T> {
-    pub fn new(id: IdType) -> Self { Tree { id, nodes: Vec::new() } }
+/// A tree that keeps the node links and values in separate vectors, see `SplitStorage`.
+pub type SplitTree<IdType, T, Ix = u32> = Tree<IdType, T, Ix, SplitStorage<T, Ix>>;
+
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> Tree<IdType, T, Ix, S> {
+    pub fn new(id: IdType) -> Self {
+        Tree {
+            id,
+            nodes: S::default(),
+            first_root: NodeIdx::none(),
+            last_root: NodeIdx::none(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns the first and last node of a sibling list, which is either the children of the
+    /// given parent or (without a parent) the roots of the tree.
+    fn list_ends_mut(&mut self, parent: NodeIdx<Ix>) -> (&mut NodeIdx<Ix>, &mut NodeIdx<Ix>) {
+        if parent.is_some() {
+            let d = self.nodes.links_mut(parent.index());
+            (&mut d.first_child, &mut d.last_child)
+        } else {
+            (&mut self.first_root, &mut self.last_root)
+        }
+    }
+
+    /// Adds the (unlinked) node to the end of the sibling list.
+    fn link_last(&mut self, parent: NodeIdx<Ix>, child: Node<IdType, Ix>) {
+        let last = *self.list_ends_mut(parent).1;
+        {
+            let d = child.valid_get_mut(self);
+            d.parent = parent;
+            d.prev_sibling = last;
+        }
+        if last.is_none() {
+            *self.list_ends_mut(parent).0 = child.as_idx();
+        } else {
+            self.nodes.links_mut(last.index()).next_sibling = child.as_idx();
+        }
+        *self.list_ends_mut(parent).1 = child.as_idx();
+    }
+
+    /// Adds the (unlinked) node to the start of the sibling list.
+    fn link_first(&mut self, parent: NodeIdx<Ix>, child: Node<IdType, Ix>) {
+        let first = *self.list_ends_mut(parent).0;
+        {
+            let d = child.valid_get_mut(self);
+            d.parent = parent;
+            d.next_sibling = first;
+        }
+        if first.is_none() {
+            *self.list_ends_mut(parent).1 = child.as_idx();
+        } else {
+            self.nodes.links_mut(first.index()).prev_sibling = child.as_idx();
+        }
+        *self.list_ends_mut(parent).0 = child.as_idx();
+    }
+
+    /// Creates a new (detached) node. Panics if the tree has reached the capacity of its index
+    /// type, see `try_create_node`.
+    pub fn create_node(&mut self, data: T) -> Node<IdType, Ix> { self.try_create_node(data).unwrap_or_else(|e| panic!("{}", e)) }
 
-    pub fn create_node(&mut self, data: T) -> Node<IdType> {
-        let idx = self.nodes.len();
-        self.nodes.push(NodeData::new(data));
-        Node { tree_id: self.id, idx }
+    /// Creates a new (detached) node, or returns an error if the tree already holds the maximum
+    /// number of nodes supported by its index type (`max_nodes`).
+    pub fn try_create_node(&mut self, data: T) -> Result<Node<IdType, Ix>, CapacityError> {
+        let idx = Ix::to_repr(self.nodes.len()).ok_or(CapacityError { max_nodes: Ix::MAX_NODES })?;
+        self.nodes.push(data);
+        Ok(Node { tree_id: self.id, idx })
     }
 
-    pub fn all_nodes(&self) -> AllNodesIter<IdType> {
+    /// Returns the number of nodes created so far (including detached nodes).
+    pub(crate) fn node_count(&self) -> usize { self.nodes.len() }
+
+    /// Removes the nodes that were created after the tree held `len` nodes. These nodes must only
+    /// be linked among themselves, not to older nodes or the roots.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.nodes.truncate(len);
+        self.debug_check("truncate");
+    }
+
+    /// Returns the maximum number of nodes this tree can hold.
+    pub fn max_nodes(&self) -> usize { Ix::MAX_NODES }
+
+    pub fn all_nodes(&self) -> AllNodesIter<IdType, Ix> {
         AllNodesIter {
             tree_id: self.id,
             cur_idx: 0,
             term_at_idx: self.nodes.len(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Adds the node (along with its subtree) after the last root of the tree, detaching it from
+    /// its current position first. Returns the node.
+    pub fn push_root(&mut self, node: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        node.remove(self);
+        self.link_last(NodeIdx::none(), node);
+        self.debug_check("push_root");
+        node
+    }
+    pub fn push_root_value(&mut self, value: T) -> Node<IdType, Ix> {
+        let n = self.create_node(value);
+        self.push_root(n)
+    }
+
+    /// Returns the roots of the tree in order.
+    pub fn roots(&self) -> ContextFreeSiblingIter<'_, IdType, T, Ix, S> {
+        ContextFreeSiblingIter {
+            next: self.first_root.as_node(self.id),
+            tree: self,
         }
     }
 
-    pub fn first_root_node(&self) -> Option<Node<IdType>> {
-        if self.nodes.len() == 0 {
+    /// Returns a standard iterator over all nodes underneath the roots of the tree (depth-first,
+    /// pre-order), one root after the other. The depth of each root is zero.
+    pub fn depth_first_search(&self) -> ContextFreeDepthFirstIter<'_, IdType, T, Ix, S> {
+        let iter = DepthFirstIter::new(self.first_root.as_node(self.id), true);
+        ContextFreeDepthFirstIter { iter, context: self }
+    }
+
+    /// Returns the first root of the tree. If no roots have been added (see `push_root`), this
+    /// returns the root of the node that was created first.
+    pub fn first_root_node(&self) -> Option<Node<IdType, Ix>> {
+        if self.first_root.is_some() {
+            self.first_root.as_node(self.id)
+        } else if self.nodes.is_empty() {
             None
         } else {
-            let first_node = Node { tree_id: self.id, idx: 0 };
+            let first_node = Node::from_index(self.id, 0);
             Some(first_node.root(self))
         }
     }
 
-    pub fn find_first<Predicate: FnMut(&Node<IdType>) -> bool>(&self, predicate: Predicate) -> Option<Node<IdType>> {
+    /// Returns the first node (in the order they were created) that matches the predicate. This
+    /// includes detached nodes, see `find_first_attached`.
+    pub fn find_first<Predicate: FnMut(&Node<IdType, Ix>) -> bool>(&self, predicate: Predicate) -> Option<Node<IdType, Ix>> {
         self.all_nodes().filter(predicate).nth(0)
     }
+
+    /// Like `find_first`, but skips detached nodes (see `Node::is_detached`). Unlike `find_first`,
+    /// the nodes are searched in pre-order, one root after the other (see `depth_first_search`).
+    pub fn find_first_attached<Predicate: FnMut(&Node<IdType, Ix>) -> bool>(&self, predicate: Predicate) -> Option<Node<IdType, Ix>> {
+        match self.implicit_root() {
+            Some(r) => r.depth_first_search_subtree(self).map(|(_, n)| n).find(predicate),
+            None => self.depth_first_search().map(|(_, n)| n).find(predicate),
+        }
+    }
+
+    /// Returns the specified node and its descendants in the order they were created (like
+    /// `all_nodes`), skipping any other node. Returns nothing if the node is detached (see
+    /// `Node::is_detached`).
+    pub fn attached_nodes(&self, root: Node<IdType, Ix>) -> AttachedNodesIter<IdType, Ix> {
+        let mut attached = Vec::new();
+        if !root.is_detached(self) {
+            attached.resize(self.nodes.len(), false);
+            self.mark_subtree(root, &mut attached);
+        }
+        AttachedNodesIter {
+            all: self.all_nodes(),
+            attached,
+        }
+    }
+
+    /// Returns the top-most node of every detached subtree, i.e. the nodes that do not have a
+    /// parent but are not roots of the tree either. These are typically left over after `remove`.
+    pub fn detached_roots(&self) -> DetachedRootsIter<'_, IdType, T, Ix, S> {
+        DetachedRootsIter {
+            all: self.all_nodes(),
+            implicit_root: self.implicit_root(),
+            tree: self,
+        }
+    }
+
+    /// Returns the root that is considered attached when no roots have been added explicitly.
+    fn implicit_root(&self) -> Option<Node<IdType, Ix>> { if self.first_root.is_none() { self.first_root_node() } else { None } }
+
+    fn mark_subtree(&self, root: Node<IdType, Ix>, marks: &mut [bool]) {
+        for (_, n) in root.depth_first_search_subtree(self) {
+            marks[n.index()] = true;
+        }
+    }
+
+    /// Returns a read-only cursor positioned at the specified node.
+    pub fn cursor(&self, node: Node<IdType, Ix>) -> TreeCursor<'_, IdType, T, Ix, S> { TreeCursor::new(self, node) }
+
+    /// Returns a cursor positioned at the specified node that can edit the tree.
+    pub fn cursor_mut(&mut self, node: Node<IdType, Ix>) -> CursorMut<'_, IdType, T, Ix, S> { CursorMut::new(self, node) }
+
+    /// Returns the node along with a reference to this tree, allowing navigation without passing
+    /// the tree to every call.
+    pub fn get(&self, node: Node<IdType, Ix>) -> NodeRef<'_, IdType, T, Ix, S> { NodeRef::new(self, node) }
+
+    /// Returns the node along with a mutable reference to this tree, for editing around the node.
+    pub fn get_mut(&mut self, node: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> { NodeMut::new(self, node) }
+
+    /// Follows the path starting at the specified node. Returns `None` if a child index along the
+    /// path is out of range.
+    pub fn resolve(&self, root: Node<IdType, Ix>, path: &NodePath) -> Option<Node<IdType, Ix>> {
+        let mut cur = root;
+        for &idx in path.indices() {
+            cur = cur.children(self).nth(idx)?;
+        }
+        Some(cur)
+    }
+
+    /// Returns mutable references to the values of several nodes at once. Returns `None` if the
+    /// same node is given more than once.
+    pub fn get_many_mut<const N: usize>(&mut self, nodes: [Node<IdType, Ix>; N]) -> Option<[&mut T; N]> {
+        for n in &nodes {
+            n.validate(self);
+        }
+        self.nodes.values_many_mut(nodes.map(|n| n.index()))
+    }
+
+    /// Verifies the links of every node in the arena: parent and child links agree, the first and
+    /// last child links match the sibling list, sibling links are symmetric, no node is listed
+    /// twice and no node is its own ancestor. The roots of the tree are checked like the children
+    /// of a parent. Returns a report of every violation that was found.
+    pub fn check_invariants(&self) -> InvariantReport<Node<IdType, Ix>> {
+        let len = self.nodes.len();
+        let node = |idx: usize| Node::from_index(self.id, idx);
+        let mut report = InvariantReport::new();
+
+        for idx in 0..len {
+            report.checked_node();
+            let d = self.nodes.links(idx);
+            let all = [d.parent, d.first_child, d.last_child, d.prev_sibling, d.next_sibling];
+            if all.iter().any(|l| l.is_some() && l.index() >= len) {
+                report.push(node(idx), ViolationKind::InvalidLink);
+            }
+        }
+
+        // Walk every sibling list, using `len` for the list of roots
+        let mut listed_in = vec![None; len];
+        for list in 0..=len {
+            let (parent, first, last) = if list == len {
+                (NodeIdx::none(), self.first_root, self.last_root)
+            } else {
+                let d = self.nodes.links(list);
+                (node(list).as_idx(), d.first_child, d.last_child)
+            };
+            let mut prev = NodeIdx::none();
+            let mut cur = first;
+            let mut complete = true;
+            while cur.is_some() && cur.index() < len {
+                let c = cur.index();
+                if listed_in[c].is_some() {
+                    report.push(node(c), ViolationKind::ListedTwice);
+                    complete = false;
+                    break;
+                }
+                listed_in[c] = Some(list);
+                let d = self.nodes.links(c);
+                if d.parent.idx != parent.idx {
+                    report.push(node(c), ViolationKind::WrongParent);
+                }
+                if d.prev_sibling.idx != prev.idx {
+                    report.push(node(c), ViolationKind::WrongPrevSibling);
+                }
+                prev = cur;
+                cur = d.next_sibling;
+            }
+            if complete && last.idx != prev.idx {
+                let at = if list == len { prev.as_node(self.id).or_else(|| last.as_node(self.id)) } else { Some(node(list)) };
+                report.push(at.unwrap(), ViolationKind::WrongLastChild);
+            }
+        }
+
+        // Parent links must be backed by the child list, and must not form a loop
+        let mut state = vec![0u8; len];
+        for (idx, &listed) in listed_in.iter().enumerate() {
+            let parent = self.nodes.links(idx).parent;
+            if parent.is_some() && parent.index() < len && listed != Some(parent.index()) {
+                report.push(node(idx), ViolationKind::NotListedByParent);
+            }
+
+            // 0 = not visited, 1 = on the current chain, 2 = known to end at a root
+            let mut chain = Vec::new();
+            let mut cur = idx;
+            loop {
+                if state[cur] != 0 {
+                    if state[cur] == 1 {
+                        report.push(node(cur), ViolationKind::Cycle);
+                    }
+                    break;
+                }
+                state[cur] = 1;
+                chain.push(cur);
+                let p = self.nodes.links(cur).parent;
+                if p.is_none() || p.index() >= len {
+                    break;
+                }
+                cur = p.index();
+            }
+            for c in chain {
+                state[c] = 2;
+            }
+        }
+        report
+    }
+
+    /// Checks the invariants after a structural change when the `debug-checks` feature is enabled
+    /// and panics if any of them is violated.
+    fn debug_check(&self, operation: &str) {
+        if cfg!(feature = "debug-checks") {
+            let report = self.check_invariants();
+            if !report.is_ok() {
+                let details: Vec<String> = report.violations().iter().map(|v| format!("node {}: {}", v.node().index(), v.kind())).collect();
+                panic!("Tree invariants violated after {}: {}", operation, details.join(", "));
+            }
+        }
+    }
+}
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> std::ops::Index<Node<IdType, Ix>> for Tree<IdType, T, Ix, S> {
+    type Output = T;
+
+    fn index(&self, node: Node<IdType, Ix>) -> &T { node.value(self) }
+}
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> std::ops::IndexMut<Node<IdType, Ix>> for Tree<IdType, T, Ix, S> {
+    fn index_mut(&mut self, node: Node<IdType, Ix>) -> &mut T { node.value_mut(self) }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
-pub struct Node<IdType: Copy + Eq> {
+pub struct Node<IdType: Copy + Eq, Ix: IndexType = u32> {
     tree_id: IdType,
-    idx: usize,
+    idx: Ix::Repr,
 }
 
-impl<IdType: Copy + Eq> Node<IdType> {
-    fn validate<T>(&self, t: &Tree<IdType, T>) {
+impl<IdType: Copy + Eq, Ix: IndexType> Node<IdType, Ix> {
+    fn validate<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) {
         if self.tree_id != t.id {
             panic!("Attempted to get a value from the wrong tree");
         }
     }
-    fn valid_get<'a, T>(&self, t: &'a Tree<IdType, T>) -> &'a NodeData<T> { &t.nodes[self.idx] }
-    fn valid_get_mut<'a, T>(&self, t: &'a mut Tree<IdType, T>) -> &'a mut NodeData<T> { &mut t.nodes[self.idx] }
-    fn get<'a, T>(&self, t: &'a Tree<IdType, T>) -> &'a NodeData<T> {
+    fn from_index(tree_id: IdType, idx: usize) -> Self {
+        Node {
+            tree_id,
+            idx: Ix::to_repr(idx).unwrap(),
+        }
+    }
+    fn index(&self) -> usize { Ix::from_repr(self.idx) }
+    fn valid_get<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> &'a NodeLinks<Ix> { t.nodes.links(self.index()) }
+    fn valid_get_mut<'a, T, S: Storage<T, Ix>>(&self, t: &'a mut Tree<IdType, T, Ix, S>) -> &'a mut NodeLinks<Ix> { t.nodes.links_mut(self.index()) }
+    fn get<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> &'a NodeLinks<Ix> {
         self.validate(t);
         self.valid_get(t)
     }
-    fn get_mut<'a, T>(&self, t: &'a mut Tree<IdType, T>) -> &'a mut NodeData<T> {
+
+    fn as_idx(&self) -> NodeIdx<Ix> { NodeIdx { idx: Some(self.idx) } }
+
+    /// Returns true if the node is part of a sibling list (it has a parent or is one of the roots).
+    fn is_linked<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> bool {
+        let d = self.get(t);
+        d.parent.is_some() || d.prev_sibling.is_some() || t.first_root.is_node(self)
+    }
+
+    /// Returns true if the node is one of the top-level roots of the tree (see `Tree::push_root`).
+    pub fn is_root<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> bool { self.get(t).parent.is_none() && self.is_linked(t) }
+
+    pub fn parent<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> { self.get(t).parent.as_node(self.tree_id) }
+    pub fn first_child<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> { self.get(t).first_child.as_node(self.tree_id) }
+    pub fn last_child<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> { self.get(t).last_child.as_node(self.tree_id) }
+    pub fn prev_sibling<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> { self.get(t).prev_sibling.as_node(self.tree_id) }
+    pub fn next_sibling<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> { self.get(t).next_sibling.as_node(self.tree_id) }
+    pub fn value<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> &'a T {
         self.validate(t);
-        self.valid_get_mut(t)
+        t.nodes.value(self.index())
+    }
+    pub fn value_mut<'a, T, S: Storage<T, Ix>>(&self, t: &'a mut Tree<IdType, T, Ix, S>) -> &'a mut T {
+        self.validate(t);
+        t.nodes.value_mut(self.index())
     }
 
-    fn as_idx(&self) -> NodeIdx { NodeIdx { idx: self.idx } }
+    /// Returns true if this node is not underneath one of the roots of the tree (see
+    /// `Tree::push_root`). If no roots have been added, the nodes underneath the root of the node
+    /// that was created first are attached (see `Tree::first_root_node`).
+    pub fn is_detached<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> bool {
+        let root = self.root(t);
+        !root.is_root(t) && t.implicit_root() != Some(root)
+    }
 
-    pub fn parent<T>(&self, t: &Tree<IdType, T>) -> Option<Node<IdType>> { self.get(t).parent.as_node(self.tree_id) }
-    pub fn first_child<T>(&self, t: &Tree<IdType, T>) -> Option<Node<IdType>> { self.get(t).first_child.as_node(self.tree_id) }
-    pub fn last_child<T>(&self, t: &Tree<IdType, T>) -> Option<Node<IdType>> { self.get(t).last_child.as_node(self.tree_id) }
-    pub fn prev_sibling<T>(&self, t: &Tree<IdType, T>) -> Option<Node<IdType>> { self.get(t).prev_sibling.as_node(self.tree_id) }
-    pub fn next_sibling<T>(&self, t: &Tree<IdType, T>) -> Option<Node<IdType>> { self.get(t).next_sibling.as_node(self.tree_id) }
-    pub fn value<'a, T>(&self, t: &'a Tree<IdType, T>) -> &'a T { &self.get(t).value }
-    pub fn value_mut<'a, T>(&self, t: &'a mut Tree<IdType, T>) -> &'a mut T { &mut self.get_mut(t).value }
+    /// Writes the subtree of this node as a Graphviz DOT graph.
+    pub fn to_dot<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>, options: &DotOptions<T>) -> String { dot::to_dot(t.get(*self), options) }
 
-    pub fn root<T>(&self, t: &Tree<IdType, T>) -> Node<IdType> {
+    pub fn root<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Node<IdType, Ix> {
         let mut result = *self;
         while let Some(p) = result.parent(t) {
             result = p;
@@ -79,9 +479,63 @@ impl<IdType: Copy + Eq> Node<IdType> {
         result
     }
 
-    pub fn remove<T>(&self, t: &mut Tree<IdType, T>) {
+    /// Returns the path from the root of this node to this node.
+    pub fn path<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> NodePath {
+        let mut indices = Vec::new();
+        let mut cur = *self;
+        while let Some(p) = cur.parent(t) {
+            let mut idx = 0;
+            let mut sib = cur;
+            while let Some(s) = sib.prev_sibling(t) {
+                idx += 1;
+                sib = s;
+            }
+            indices.push(idx);
+            cur = p;
+        }
+        indices.reverse();
+        NodePath::from(indices)
+    }
+
+    /// Follows the keys downward from this node, at each level picking the first child whose key
+    /// (as returned by `key_fn`) matches. Returns `None` if a key is not found.
+    pub fn find_path<T, S: Storage<T, Ix>, K: ?Sized + Eq, I, F>(&self, t: &Tree<IdType, T, Ix, S>, keys: I, key_fn: F) -> Option<Node<IdType, Ix>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+        F: Fn(&T) -> &K,
+    {
+        let mut cur = *self;
+        for key in keys {
+            cur = cur.children(t).find(|c| key_fn(c.value(t)) == key.borrow())?;
+        }
+        Some(cur)
+    }
+
+    /// Same as `find_path`, except that missing nodes are created (as the last child) with the value
+    /// returned by `make_value` for the missing key. Returns the node for the last key.
+    pub fn get_or_create_path<T, S: Storage<T, Ix>, K: ?Sized + Eq, I, F, M>(&self, t: &mut Tree<IdType, T, Ix, S>, keys: I, key_fn: F, mut make_value: M) -> Node<IdType, Ix>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+        F: Fn(&T) -> &K,
+        M: FnMut(&K) -> T,
+    {
+        let mut cur = *self;
+        for key in keys {
+            let key = key.borrow();
+            cur = match cur.children(t).find(|c| key_fn(c.value(t)) == key) {
+                Some(c) => c,
+                None => cur.append_child_value(t, make_value(key)),
+            };
+        }
+        cur
+    }
+
+    /// Detaches this node (along with its subtree) from its parent, or from the roots of the tree.
+    pub fn remove<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>) {
         let indexes = self.get(t).as_indexes();
-        if indexes.parent.is_some() {
+        if self.is_linked(t) {
             // Fix-up self
             {
                 let d = self.valid_get_mut(t);
@@ -89,165 +543,468 @@ impl<IdType: Copy + Eq> Node<IdType> {
                 d.prev_sibling = NodeIdx::none();
                 d.next_sibling = NodeIdx::none();
             }
-            // Fix-up parent
+            // Fix-up parent (or the roots of the tree)
             {
-                let d = &mut t.nodes[indexes.parent.idx];
-                if d.first_child.is_node(self) {
-                    d.first_child = indexes.next_sibling;
+                let (first, last) = t.list_ends_mut(indexes.parent);
+                if first.is_node(self) {
+                    *first = indexes.next_sibling;
                 }
-                if d.last_child.is_node(self) {
-                    d.last_child = indexes.prev_sibling;
+                if last.is_node(self) {
+                    *last = indexes.prev_sibling;
                 }
             }
             // Fix-up prev sibling
             if indexes.prev_sibling.is_some() {
-                let d = &mut t.nodes[indexes.prev_sibling.idx];
+                let d = t.nodes.links_mut(indexes.prev_sibling.index());
                 d.next_sibling = indexes.next_sibling;
             }
             // Fix-up next sibling
             if indexes.next_sibling.is_some() {
-                let d = &mut t.nodes[indexes.next_sibling.idx];
+                let d = t.nodes.links_mut(indexes.next_sibling.index());
                 d.prev_sibling = indexes.prev_sibling;
             }
         }
+        t.debug_check("remove");
     }
 
-    pub fn append_child<T>(&self, t: &mut Tree<IdType, T>, child: Node<IdType>) -> Node<IdType> {
+    pub fn append_child<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child: Node<IdType, Ix>) -> Node<IdType, Ix> {
         self.validate(t);
         child.remove(t);
-
-        let last_child = self.valid_get(t).last_child;
-        if last_child.is_none() {
-            // No existing children
-            child.valid_get_mut(t).parent = self.as_idx();
-            let d = self.valid_get_mut(t);
-            d.first_child = child.as_idx();
-            d.last_child = child.as_idx();
-        } else {
-            // Update current last child
-            t.nodes[last_child.idx].next_sibling = child.as_idx();
-            // Update the new child
-            {
-                let d = child.valid_get_mut(t);
-                d.prev_sibling = last_child;
-                d.parent = self.as_idx();
-            }
-            // Update self
-            self.valid_get_mut(t).last_child = child.as_idx();
-        }
+        t.link_last(self.as_idx(), child);
+        t.debug_check("append_child");
         child
     }
-    pub fn append_child_value<T>(&self, t: &mut Tree<IdType, T>, child_value: T) -> Node<IdType> {
+    pub fn append_child_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child_value: T) -> Node<IdType, Ix> {
         let n = t.create_node(child_value);
         self.append_child(t, n);
         n
     }
 
-    pub fn prepend_child<T>(&self, t: &mut Tree<IdType, T>, child: Node<IdType>) -> Node<IdType> {
+    pub fn prepend_child<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child: Node<IdType, Ix>) -> Node<IdType, Ix> {
         self.validate(t);
         child.remove(t);
-
-        let first_child = self.valid_get(t).first_child;
-        if first_child.is_none() {
-            // No existing children
-            child.valid_get_mut(t).parent = self.as_idx();
-            let d = self.valid_get_mut(t);
-            d.first_child = child.as_idx();
-            d.last_child = child.as_idx();
-        } else {
-            // Update current first child
-            t.nodes[first_child.idx].prev_sibling = child.as_idx();
-            // Update the new child
-            {
-                let d = child.valid_get_mut(t);
-                d.next_sibling = first_child;
-                d.parent = self.as_idx();
-            }
-            // Update self
-            self.valid_get_mut(t).first_child = child.as_idx();
-        }
+        t.link_first(self.as_idx(), child);
+        t.debug_check("prepend_child");
         child
     }
-    pub fn prepend_child_value<T>(&self, t: &mut Tree<IdType, T>, child_value: T) -> Node<IdType> {
+    pub fn prepend_child_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child_value: T) -> Node<IdType, Ix> {
         let n = t.create_node(child_value);
         self.prepend_child(t, n);
         n
     }
 
-    /// Inserts the specified record immediately after this record. If this record does not have a
-    /// parent, then this method will panic.
-    pub fn insert_next_sibling<T>(&self, t: &mut Tree<IdType, T>, child: Node<IdType>) -> Node<IdType> {
-        let parent = self.parent(t).expect("This node must have a parent node for insert_next_sibling to work.");
-        if parent.last_child(t).unwrap() == *self {
-            // Current node is the last on in the list, this is the same as calling append_child on the parent
-            parent.append_child(t, child)
-        } else {
+    /// Inserts the specified record immediately after this record. If this record neither has a
+    /// parent nor is one of the roots of the tree, then this method will panic.
+    pub fn insert_next_sibling<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        if !self.is_linked(t) {
+            panic!("This node must have a parent node (or be a root of the tree) for insert_next_sibling to work.");
+        }
+        if child == *self {
+            return child;
+        }
+        // Note: The child is removed first since it may currently be the next sibling of this node
+        child.remove(t);
+        let parent = self.valid_get(t).parent;
+        if let Some(cur_next) = self.next_sibling(t) {
             // In this case, the parent node does not need to be updated.
-            self.validate(t);
-            child.remove(t);
-
-            let cur_next = self.next_sibling(t).unwrap();
-
             {
                 let d = child.valid_get_mut(t);
-                d.parent = parent.as_idx();
+                d.parent = parent;
                 d.prev_sibling = self.as_idx();
                 d.next_sibling = cur_next.as_idx();
             }
             self.valid_get_mut(t).next_sibling = child.as_idx();
             cur_next.valid_get_mut(t).prev_sibling = child.as_idx();
-
-            child
+        } else {
+            // Current node is the last one in the list, this is the same as calling append_child on the parent
+            t.link_last(parent, child);
         }
+        t.debug_check("insert_next_sibling");
+        child
     }
-    pub fn insert_next_sibling_value<T>(&self, t: &mut Tree<IdType, T>, child_value: T) -> Node<IdType> {
+    pub fn insert_next_sibling_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child_value: T) -> Node<IdType, Ix> {
         let n = t.create_node(child_value);
         self.insert_next_sibling(t, n);
         n
     }
 
-    /// Inserts the specified record immediately after this record. If this record does not have a
-    /// parent, then this method will panic.
-    pub fn insert_prev_sibling<T>(&self, t: &mut Tree<IdType, T>, child: Node<IdType>) -> Node<IdType> {
-        let parent = self.parent(t).expect("This node must have a parent node for insert_prev_sibling to work.");
-        if parent.first_child(t).unwrap() == *self {
-            // Current node is the first on in the list, this is the same as calling prepend_child on the parent
-            parent.prepend_child(t, child)
-        } else {
+    /// Inserts the specified record immediately before this record. If this record neither has a
+    /// parent nor is one of the roots of the tree, then this method will panic.
+    pub fn insert_prev_sibling<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        if !self.is_linked(t) {
+            panic!("This node must have a parent node (or be a root of the tree) for insert_prev_sibling to work.");
+        }
+        if child == *self {
+            return child;
+        }
+        // Note: The child is removed first since it may currently be the previous sibling of this node
+        child.remove(t);
+        let parent = self.valid_get(t).parent;
+        if let Some(cur_next) = self.prev_sibling(t) {
             // In this case, the parent node does not need to be updated.
-            self.validate(t);
-            child.remove(t);
-
-            let cur_next = self.prev_sibling(t).unwrap();
-
             {
                 let d = child.valid_get_mut(t);
-                d.parent = parent.as_idx();
+                d.parent = parent;
                 d.prev_sibling = cur_next.as_idx();
                 d.next_sibling = self.as_idx();
             }
             self.valid_get_mut(t).prev_sibling = child.as_idx();
             cur_next.valid_get_mut(t).next_sibling = child.as_idx();
-
-            child
+        } else {
+            // Current node is the first one in the list, this is the same as calling prepend_child on the parent
+            t.link_first(parent, child);
         }
+        t.debug_check("insert_prev_sibling");
+        child
     }
-    pub fn insert_prev_sibling_value<T>(&self, t: &mut Tree<IdType, T>, child_value: T) -> Node<IdType> {
+    pub fn insert_prev_sibling_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, child_value: T) -> Node<IdType, Ix> {
         let n = t.create_node(child_value);
         self.insert_prev_sibling(t, n);
         n
     }
 
+    /// Replaces this node with its children (in order). This node is left detached without any
+    /// children. If this node is detached, then the children become detached as well.
+    pub fn unwrap<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>) {
+        let linked = self.is_linked(t);
+        while let Some(c) = self.first_child(t) {
+            if linked {
+                self.insert_prev_sibling(t, c);
+            } else {
+                c.remove(t);
+            }
+        }
+        self.remove(t);
+    }
+
+    /// Inserts the new parent in this node's position and then moves this node underneath it (as
+    /// its last child). Returns the new parent.
+    ///
+    /// Panics if the new parent is this node or one of its ancestors.
+    pub fn wrap_with<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, new_parent: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        if new_parent == *self || new_parent.is_ancestor_of(t, *self) {
+            panic!("Unable to wrap a node with itself or one of its ancestors");
+        }
+        if self.is_linked(t) {
+            self.insert_prev_sibling(t, new_parent);
+        } else {
+            new_parent.remove(t);
+        }
+        new_parent.append_child(t, *self);
+        new_parent
+    }
+    pub fn wrap_with_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, new_parent_value: T) -> Node<IdType, Ix> {
+        let n = t.create_node(new_parent_value);
+        self.wrap_with(t, n)
+    }
+
+    /// Moves the other node into this node's exact position, leaving this node (and its subtree)
+    /// detached. Returns the other node.
+    ///
+    /// Panics if the other node is an ancestor of this node.
+    pub fn replace_with<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, other: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        if other.is_ancestor_of(t, *self) {
+            panic!("Unable to replace a node with one of its ancestors");
+        }
+        if other != *self {
+            if self.is_linked(t) {
+                self.insert_prev_sibling(t, other);
+            } else {
+                other.remove(t);
+            }
+            self.remove(t);
+        }
+        other
+    }
+
+    /// Exchanges the positions of two nodes (along with their subtrees). Panics if one of the
+    /// nodes is an ancestor of the other.
+    pub fn swap<T, S: Storage<T, Ix>>(t: &mut Tree<IdType, T, Ix, S>, a: Node<IdType, Ix>, b: Node<IdType, Ix>) {
+        if a == b {
+            return;
+        }
+        if a.is_ancestor_of(t, b) || b.is_ancestor_of(t, a) {
+            panic!("Unable to swap a node with its own ancestor");
+        }
+
+        let a_parent = a.parent(t);
+        let a_root = a.is_root(t);
+        let a_next = a.next_sibling(t);
+        if a_next == Some(b) {
+            // Adjacent siblings, moving a after b is sufficient
+            b.insert_next_sibling(t, a);
+            return;
+        }
+
+        // Move a into b's position
+        if b.is_linked(t) {
+            b.insert_prev_sibling(t, a);
+        } else {
+            a.remove(t);
+        }
+
+        // Move b into a's old position
+        if let Some(n) = a_next {
+            n.insert_prev_sibling(t, b);
+        } else if let Some(p) = a_parent {
+            p.append_child(t, b);
+        } else if a_root {
+            t.push_root(b);
+        } else {
+            b.remove(t);
+        }
+    }
+
+    /// Moves the contiguous run of siblings from `first` through `last` (inclusive) to the given
+    /// position underneath the new parent. The sibling links are spliced as a unit, so only the
+    /// parent link of each moved node needs to be updated.
+    ///
+    /// Panics if `last` is not a following sibling of `first`, if the new parent is part of one of
+    /// the moved subtrees or if the position refers to a node that is not a child of the new parent.
+    pub fn move_sibling_range<T, S: Storage<T, Ix>>(t: &mut Tree<IdType, T, Ix, S>, first: Node<IdType, Ix>, last: Node<IdType, Ix>, new_parent: Node<IdType, Ix>, position: InsertPosition<Node<IdType, Ix>>) {
+        first.validate(t);
+        last.validate(t);
+        new_parent.validate(t);
+        let old_parent = first.parent(t);
+
+        // Find the ancestor (or self) of the new parent that shares the range's sibling list
+        let mut anchor = Some(new_parent);
+        while let Some(a) = anchor {
+            if a.parent(t) == old_parent {
+                break;
+            }
+            anchor = a.parent(t);
+        }
+        let target = match position {
+            InsertPosition::Before(n) | InsertPosition::After(n) => {
+                if n.parent(t) != Some(new_parent) {
+                    panic!("The insert position must refer to a child of the new parent");
+                }
+                Some(n)
+            }
+            InsertPosition::First | InsertPosition::Last => None,
+        };
+
+        // Validate the range before any links are altered
+        let mut cur = first;
+        loop {
+            if Some(cur) == anchor {
+                panic!("Unable to move a sibling range underneath itself");
+            }
+            if Some(cur) == target {
+                panic!("The insert position must not be part of the moved range");
+            }
+            if cur == last {
+                break;
+            }
+            cur = cur.next_sibling(t).expect("The last node must be a following sibling of the first node");
+        }
+
+        // Detach the range from its current sibling list
+        let prev = first.prev_sibling(t);
+        let next = last.next_sibling(t);
+        {
+            let (list_first, list_last) = t.list_ends_mut(NodeIdx::from_node(old_parent));
+            if list_first.is_node(&first) {
+                *list_first = NodeIdx::from_node(next);
+            }
+            if list_last.is_node(&last) {
+                *list_last = NodeIdx::from_node(prev);
+            }
+        }
+        if let Some(s) = prev {
+            s.valid_get_mut(t).next_sibling = NodeIdx::from_node(next);
+        }
+        if let Some(s) = next {
+            s.valid_get_mut(t).prev_sibling = NodeIdx::from_node(prev);
+        }
+
+        // Splice the range into its new position
+        let (prev, next) = match position {
+            InsertPosition::First => (None, new_parent.first_child(t)),
+            InsertPosition::Last => (new_parent.last_child(t), None),
+            InsertPosition::Before(n) => (n.prev_sibling(t), Some(n)),
+            InsertPosition::After(n) => (Some(n), n.next_sibling(t)),
+        };
+        first.valid_get_mut(t).prev_sibling = NodeIdx::from_node(prev);
+        last.valid_get_mut(t).next_sibling = NodeIdx::from_node(next);
+        if let Some(s) = prev {
+            s.valid_get_mut(t).next_sibling = first.as_idx();
+        } else {
+            new_parent.valid_get_mut(t).first_child = first.as_idx();
+        }
+        if let Some(s) = next {
+            s.valid_get_mut(t).prev_sibling = last.as_idx();
+        } else {
+            new_parent.valid_get_mut(t).last_child = last.as_idx();
+        }
+
+        let mut cur = first;
+        loop {
+            cur.valid_get_mut(t).parent = new_parent.as_idx();
+            if cur == last {
+                break;
+            }
+            cur = cur.next_sibling(t).unwrap();
+        }
+        t.debug_check("move_sibling_range");
+    }
+
+    /// Moves all siblings following this node to the end of the new parent's children. Returns
+    /// the new parent.
+    ///
+    /// The new parent is not moved, so it stays wherever the caller put it (for example, a detached
+    /// node that is inserted with `insert_next_sibling` afterwards). `split_children_after_value`
+    /// creates the new parent instead.
+    pub fn split_children_after<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, new_parent: Node<IdType, Ix>) -> Node<IdType, Ix> {
+        if let Some(first) = self.next_sibling(t) {
+            let last = match self.parent(t) {
+                Some(p) => p.last_child(t),
+                None => t.last_root.as_node(t.id),
+            };
+            Node::move_sibling_range(t, first, last.unwrap(), new_parent, InsertPosition::Last);
+        }
+        new_parent
+    }
+    pub fn split_children_after_value<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, new_parent_value: T) -> Node<IdType, Ix> {
+        let n = t.create_node(new_parent_value);
+        self.split_children_after(t, n)
+    }
+
+    /// Returns true if this node is a (strict) ancestor of the other node. This walks the parents
+    /// of the other node, see `LcaIndex` for repeated queries.
+    pub fn is_ancestor_of<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>, other: Node<IdType, Ix>) -> bool {
+        let mut cur = other.parent(t);
+        while let Some(p) = cur {
+            if p == *self {
+                return true;
+            }
+            cur = p.parent(t);
+        }
+        false
+    }
+
+    /// Returns the number of ancestors of this node (zero for a root).
+    pub fn depth<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> usize {
+        let mut depth = 0;
+        let mut cur = self.parent(t);
+        while let Some(p) = cur {
+            depth += 1;
+            cur = p.parent(t);
+        }
+        depth
+    }
+
+    /// Returns the deepest node that is an ancestor of (or equal to) both nodes, or `None` if the
+    /// nodes are not connected. This walks the parents of both nodes, see `LcaIndex` for repeated
+    /// queries.
+    pub fn lowest_common_ancestor<T, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> Option<Node<IdType, Ix>> {
+        let (mut a, mut b) = (a, b);
+        let (mut da, mut db) = (a.depth(t), b.depth(t));
+        while da > db {
+            a = a.parent(t)?;
+            da -= 1;
+        }
+        while db > da {
+            b = b.parent(t)?;
+            db -= 1;
+        }
+        while a != b {
+            a = a.parent(t)?;
+            b = b.parent(t)?;
+        }
+        Some(a)
+    }
+
+    /// Compares the positions of the nodes in document order (pre-order): ancestors come before
+    /// their descendants and earlier siblings (along with their subtrees) before later ones. Nodes
+    /// that are not connected are ordered by their roots in an unspecified but consistent way.
+    /// This walks the parents of both nodes, see `OrderIndex` for repeated queries.
+    pub fn compare_document_order<T, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        if a == b {
+            return Ordering::Equal;
+        }
+        let chain = |n: Node<IdType, Ix>| {
+            let mut result = vec![n];
+            while let Some(p) = result.last().unwrap().parent(t) {
+                result.push(p);
+            }
+            result.reverse();
+            result
+        };
+        let (ca, cb) = (chain(a), chain(b));
+        let common = ca.iter().zip(&cb).take_while(|&(x, y)| x == y).count();
+        if common == ca.len() {
+            // a is an ancestor of b
+            return Ordering::Less;
+        } else if common == cb.len() {
+            return Ordering::Greater;
+        }
+        // Walk forward from both diverging nodes, whichever reaches the other one comes first
+        let (sa, sb) = (ca[common], cb[common]);
+        let (mut x, mut y) = (Some(sa), Some(sb));
+        while x.is_some() || y.is_some() {
+            x = x.and_then(|n| n.next_sibling(t));
+            y = y.and_then(|n| n.next_sibling(t));
+            if x == Some(sb) {
+                return Ordering::Less;
+            } else if y == Some(sa) {
+                return Ordering::Greater;
+            }
+        }
+        sa.index().cmp(&sb.index())
+    }
+
+    /// Returns the number of edges on the path between the two nodes, or `None` if the nodes are
+    /// not connected.
+    pub fn distance<T, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> Option<usize> {
+        let lca = Node::lowest_common_ancestor(t, a, b)?;
+        let dl = lca.depth(t);
+        Some(a.depth(t) - dl + b.depth(t) - dl)
+    }
+
     /// Removes all child nodes from this node
-    pub fn remove_children<T>(&self, t: &mut Tree<IdType, T>) {
+    pub fn remove_children<T, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>) {
         while let Some(c) = self.first_child(t) {
             c.remove(t);
         }
     }
 
+    /// Removes every descendant of this node for which the predicate returns false. Descendants
+    /// are visited in pre-order and this node itself is never tested. The mode determines whether
+    /// the children of a rejected node are removed with it or hoisted into its position.
+    pub fn retain_descendants<T, S: Storage<T, Ix>, Predicate>(&self, t: &mut Tree<IdType, T, Ix, S>, mode: RetainMode, mut predicate: Predicate)
+    where
+        Predicate: FnMut(&Tree<IdType, T, Ix, S>, Node<IdType, Ix>) -> bool,
+    {
+        let mut next = self.first_child(t);
+        while let Some(n) = next {
+            if predicate(t, n) {
+                next = n.first_child(t).or_else(|| n.next_in_subtree(t, *self));
+            } else {
+                let first_child = n.first_child(t);
+                if mode == RetainMode::HoistChildren && first_child.is_some() {
+                    while let Some(c) = n.first_child(t) {
+                        n.insert_prev_sibling(t, c);
+                    }
+                    next = first_child;
+                } else {
+                    next = n.next_in_subtree(t, *self);
+                }
+                n.remove(t);
+            }
+        }
+    }
+
+    /// Returns the next node in pre-order after this node's subtree, stopping at the given root.
+    fn next_in_subtree<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Option<Node<IdType, Ix>> {
+        t.get(*self).next_in_subtree(Some(&t.get(root))).map(|n| n.node())
+    }
+
     /// Returns a standard iterator for all children of this node. Holds a reference to the tree
     /// for the duration of the iterator.
-    pub fn children<'a, T>(&self, t: &'a Tree<IdType, T>) -> ContextFreeSiblingIter<'a, IdType, T> {
+    pub fn children<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> ContextFreeSiblingIter<'a, IdType, T, Ix, S> {
         let next = self.first_child(t);
         ContextFreeSiblingIter { next, tree: t }
     }
@@ -261,46 +1018,67 @@ impl<IdType: Copy + Eq> Node<IdType> {
     /// still upheld, but the results will not be as expected or may not terminate.
     ///
     /// Be sure to either import trees::ContextIterator or use the next_value method.
-    pub fn children_mut<T>(&self, t: &Tree<IdType, T>) -> SiblingIter<IdType, T> { SiblingIter::new(self.first_child(t)) }
+    pub fn children_mut<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> SiblingIter<IdType, T, Ix, S> { SiblingIter::new(self.first_child(t)) }
 
     /// Returns a standard iterator starting with the current node, expanding to all nodes
     /// underneath this node. The iterator returns a tuple containing the depth (starting with
-    /// zero) and the current node.
+    /// zero) and the current node. The search then continues with the following siblings of this
+    /// node (and the nodes underneath them, at depth zero for the siblings), use
+    /// `depth_first_search_subtree` to stop at the end of this node's subtree.
     ///
     /// This is a depth-first search using pre-order.
-    pub fn depth_first_search<'a, T>(&self, t: &'a Tree<IdType, T>) -> ContextFreeDepthFirstIter<'a, IdType, T> {
+    pub fn depth_first_search<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> ContextFreeDepthFirstIter<'a, IdType, T, Ix, S> {
         let iter = self.depth_first_search_mut();
         ContextFreeDepthFirstIter { iter, context: t }
     }
 
-    pub fn depth_first_search_mut<T>(&self) -> DepthFirstIter<IdType, T> { DepthFirstIter::new(*self) }
+    pub fn depth_first_search_mut<T, S: Storage<T, Ix>>(&self) -> DepthFirstIter<IdType, T, Ix, S> { DepthFirstIter::new(Some(*self), true) }
+
+    /// Returns a standard iterator over this node and all nodes underneath it (depth-first,
+    /// pre-order). Unlike `depth_first_search`, the siblings of this node are not included.
+    pub fn depth_first_search_subtree<'a, T, S: Storage<T, Ix>>(&self, t: &'a Tree<IdType, T, Ix, S>) -> ContextFreeDepthFirstIter<'a, IdType, T, Ix, S> {
+        let iter = self.depth_first_search_subtree_mut();
+        ContextFreeDepthFirstIter { iter, context: t }
+    }
+
+    pub fn depth_first_search_subtree_mut<T, S: Storage<T, Ix>>(&self) -> DepthFirstIter<IdType, T, Ix, S> { DepthFirstIter::new(Some(*self), false) }
+
+    /// Returns a lending iterator over this node and all nodes underneath it (depth-first,
+    /// pre-order), yielding the depth, the node and a mutable reference to its value. Since each
+    /// value reference borrows the iterator, use `while let Some(..) = iter.next_value()`.
+    pub fn values_mut<'a, T, S: Storage<T, Ix>>(&self, t: &'a mut Tree<IdType, T, Ix, S>) -> ValuesMut<'a, IdType, T, Ix, S> {
+        self.validate(t);
+        ValuesMut {
+            iter: self.depth_first_search_subtree_mut(),
+            tree: t,
+        }
+    }
 
-    pub fn find_first<T, Predicate: FnMut(&(usize, Node<IdType>)) -> bool>(&self, t: &Tree<IdType, T>, predicate: Predicate) -> Option<(usize, Node<IdType>)> {
+    pub fn find_first<T, S: Storage<T, Ix>, Predicate: FnMut(&(usize, Node<IdType, Ix>)) -> bool>(&self, t: &Tree<IdType, T, Ix, S>, predicate: Predicate) -> Option<(usize, Node<IdType, Ix>)> {
         self.depth_first_search(t).filter(predicate).nth(0)
     }
 
     //pub fn breadth_first_search
 }
 
-struct NodeIndexes {
-    parent: NodeIdx,
-    prev_sibling: NodeIdx,
-    next_sibling: NodeIdx,
+struct NodeIndexes<Ix: IndexType> {
+    parent: NodeIdx<Ix>,
+    prev_sibling: NodeIdx<Ix>,
+    next_sibling: NodeIdx<Ix>,
 }
 
-#[derive(Clone)]
-struct NodeData<T> {
-    value: T,
-    parent: NodeIdx,
-    first_child: NodeIdx,
-    last_child: NodeIdx,
-    prev_sibling: NodeIdx,
-    next_sibling: NodeIdx,
-}
-impl<T> NodeData<T> {
-    pub fn new(value: T) -> Self {
+/// The links of a single node within an arena tree.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeLinks<Ix: IndexType> {
+    parent: NodeIdx<Ix>,
+    first_child: NodeIdx<Ix>,
+    last_child: NodeIdx<Ix>,
+    prev_sibling: NodeIdx<Ix>,
+    next_sibling: NodeIdx<Ix>,
+}
+impl<Ix: IndexType> NodeLinks<Ix> {
+    fn new() -> Self {
         Self {
-            value,
             parent: NodeIdx::none(),
             first_child: NodeIdx::none(),
             last_child: NodeIdx::none(),
@@ -308,7 +1086,7 @@ impl<T> NodeData<T> {
             next_sibling: NodeIdx::none(),
         }
     }
-    pub fn as_indexes(&self) -> NodeIndexes {
+    fn as_indexes(&self) -> NodeIndexes<Ix> {
         NodeIndexes {
             parent: self.parent,
             prev_sibling: self.prev_sibling,
@@ -318,141 +1096,168 @@ impl<T> NodeData<T> {
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
-struct NodeIdx {
-    idx: usize,
+struct NodeIdx<Ix: IndexType> {
+    idx: Option<Ix::Repr>,
 }
-impl NodeIdx {
-    pub fn none() -> Self { Self { idx: std::usize::MAX } }
-    pub fn is_none(&self) -> bool { self.idx == std::usize::MAX }
+impl<Ix: IndexType> NodeIdx<Ix> {
+    pub fn none() -> Self { Self { idx: None } }
+    pub fn is_none(&self) -> bool { self.idx.is_none() }
     pub fn is_some(&self) -> bool { !self.is_none() }
-    pub fn is_node<IdType: Copy + Eq>(&self, n: &Node<IdType>) -> bool { self.idx == n.idx }
-    pub fn as_node<IdType: Copy + Eq>(&self, tree_id: IdType) -> Option<Node<IdType>> {
-        if self.is_none() {
-            None
-        } else {
-            Some(Node {
-                tree_id: tree_id,
-                idx: self.idx,
-            })
-        }
-    }
+    /// Returns the position of the node within the tree. Must only be called if `is_some`.
+    pub fn index(&self) -> usize { Ix::from_repr(self.idx.unwrap()) }
+    pub fn is_node<IdType: Copy + Eq>(&self, n: &Node<IdType, Ix>) -> bool { self.idx == Some(n.idx) }
+    pub fn from_node<IdType: Copy + Eq>(n: Option<Node<IdType, Ix>>) -> Self { n.map_or_else(Self::none, |n| n.as_idx()) }
+    pub fn as_node<IdType: Copy + Eq>(&self, tree_id: IdType) -> Option<Node<IdType, Ix>> { self.idx.map(|idx| Node { tree_id, idx }) }
 }
 
-pub struct DepthFirstIter<IdType: Copy + Eq, T> {
-    next: Option<Node<IdType>>,
-    parents: Vec<Node<IdType>>,
-    _marker: std::marker::PhantomData<T>,
+pub struct DepthFirstIter<IdType: Copy + Eq, T, Ix: IndexType = u32, S: Storage<T, Ix> = InterleavedStorage<T, Ix>> {
+    next: Option<Node<IdType, Ix>>,
+    parents: Vec<Node<IdType, Ix>>,
+    /// Continue with the following siblings of the starting node.
+    follow_siblings: bool,
+    _marker: std::marker::PhantomData<(T, S)>,
 }
-impl<IdType: Copy + Eq, T> DepthFirstIter<IdType, T> {
-    fn new(root: Node<IdType>) -> Self {
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> DepthFirstIter<IdType, T, Ix, S> {
+    fn new(root: Option<Node<IdType, Ix>>, follow_siblings: bool) -> Self {
         Self {
-            next: Some(root),
+            next: root,
             parents: Vec::new(),
+            follow_siblings,
             _marker: std::marker::PhantomData,
         }
     }
 
-    pub fn next_value(&mut self, t: &Tree<IdType, T>) -> Option<(usize, Node<IdType>)> {
+    pub fn next_value(&mut self, t: &Tree<IdType, T, Ix, S>) -> Option<(usize, Node<IdType, Ix>)> {
         if let Some(n) = self.next {
             let depth = self.parents.len();
-            let result = Some((depth, n));
             if let Some(child) = n.first_child(t) {
                 // Node has children, start there next time
                 self.parents.push(n);
                 self.next = Some(child);
-            } else if let Some(sib) = n.next_sibling(t) {
-                // Node has a sibling, start there next time
-                self.next = Some(sib);
             } else {
-                // No more children/siblings to process, find a parent's sibling to process
-                let mut parent = self.parents.pop();
-                while let Some(p) = parent {
-                    if let Some(s) = p.next_sibling(t) {
-                        self.next = Some(s);
-                        return result;
-                    }
-                    parent = self.parents.pop();
-                }
-                self.next = None;
+                self.advance_past(t, n);
             }
-            result
+            Some((depth, n))
         } else {
             None
         }
     }
 
-    pub fn discard_child_results(&mut self, t: &Tree<IdType, T>, cur_depth: usize) {
+    pub fn discard_child_results(&mut self, t: &Tree<IdType, T, Ix, S>, cur_depth: usize) {
         if self.parents.len() > cur_depth {
-            let mut parent = self.parents.pop();
-            while self.parents.len() > cur_depth {
-                parent = self.parents.pop();
-            }
+            self.parents.truncate(cur_depth + 1);
+            let n = self.parents.pop().unwrap();
+            self.advance_past(t, n);
+        }
+    }
 
-            // Note: Copied from the next_value function
-            while let Some(p) = parent {
-                if let Some(s) = p.next_sibling(t) {
-                    self.next = Some(s);
-                    return;
-                }
-                parent = self.parents.pop();
+    /// Finds the next sibling of the node (or of its closest ancestor that has one) without
+    /// leaving the subtree of the starting node (unless following its siblings).
+    fn advance_past(&mut self, t: &Tree<IdType, T, Ix, S>, mut n: Node<IdType, Ix>) {
+        loop {
+            if self.parents.is_empty() {
+                // Back at the starting level, continue with the next sibling if requested
+                self.next = if self.follow_siblings { n.next_sibling(t) } else { None };
+                return;
             }
-            self.next = None;
+            if let Some(s) = n.next_sibling(t) {
+                self.next = Some(s);
+                return;
+            }
+            n = self.parents.pop().unwrap();
         }
     }
 }
-impl<IdType: Copy + Eq, T> ContextIterator<Tree<IdType, T>> for DepthFirstIter<IdType, T> {
-    type Item = (usize, Node<IdType>);
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> ContextIterator<Tree<IdType, T, Ix, S>> for DepthFirstIter<IdType, T, Ix, S> {
+    type Item = (usize, Node<IdType, Ix>);
 
-    fn next(&mut self, t: &Tree<IdType, T>) -> Option<Self::Item> { self.next_value(t) }
+    fn next(&mut self, t: &Tree<IdType, T, Ix, S>) -> Option<Self::Item> { self.next_value(t) }
 }
 
-pub struct ContextFreeDepthFirstIter<'a, IdType: Copy + Eq + 'a, T: 'a> {
-    iter: DepthFirstIter<IdType, T>,
-    context: &'a Tree<IdType, T>,
+pub struct ContextFreeDepthFirstIter<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    iter: DepthFirstIter<IdType, T, Ix, S>,
+    context: &'a Tree<IdType, T, Ix, S>,
 }
-impl<'a, IdType: Copy + Eq + 'a, T: 'a> ContextFreeDepthFirstIter<'a, IdType, T> {
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> ContextFreeDepthFirstIter<'a, IdType, T, Ix, S> {
     pub fn discard_child_results(&mut self, cur_depth: usize) { self.iter.discard_child_results(self.context, cur_depth) }
 }
-impl<'a, IdType: Copy + Eq + 'a, T: 'a> std::iter::Iterator for ContextFreeDepthFirstIter<'a, IdType, T> {
-    type Item = (usize, Node<IdType>);
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for ContextFreeDepthFirstIter<'a, IdType, T, Ix, S> {
+    type Item = (usize, Node<IdType, Ix>);
 
     fn next(&mut self) -> Option<Self::Item> { self.iter.next_value(self.context) }
 }
 
-pub struct AllNodesIter<IdType: Copy + Eq> {
+pub struct ValuesMut<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    iter: DepthFirstIter<IdType, T, Ix, S>,
+    tree: &'a mut Tree<IdType, T, Ix, S>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> ValuesMut<'a, IdType, T, Ix, S> {
+    pub fn next_value(&mut self) -> Option<(usize, Node<IdType, Ix>, &mut T)> {
+        let (depth, n) = self.iter.next_value(self.tree)?;
+        Some((depth, n, self.tree.nodes.value_mut(n.index())))
+    }
+    pub fn discard_child_results(&mut self, cur_depth: usize) { self.iter.discard_child_results(self.tree, cur_depth) }
+}
+
+pub struct AllNodesIter<IdType: Copy + Eq, Ix: IndexType = u32> {
     tree_id: IdType,
     cur_idx: usize,
     term_at_idx: usize,
+    _marker: std::marker::PhantomData<Ix>,
 }
-impl<IdType: Copy + Eq> std::iter::Iterator for AllNodesIter<IdType> {
-    type Item = Node<IdType>;
+impl<IdType: Copy + Eq, Ix: IndexType> std::iter::Iterator for AllNodesIter<IdType, Ix> {
+    type Item = Node<IdType, Ix>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.cur_idx == self.term_at_idx {
             None
         } else {
-            let n = Node {
-                tree_id: self.tree_id.clone(),
-                idx: self.cur_idx,
-            };
+            let n = Node::from_index(self.tree_id, self.cur_idx);
             self.cur_idx += 1;
             Some(n)
         }
     }
 }
 
-pub struct SiblingIter<IdType: Copy + Eq, T> {
-    next: Option<Node<IdType>>,
-    _marker: std::marker::PhantomData<T>,
+pub struct AttachedNodesIter<IdType: Copy + Eq, Ix: IndexType = u32> {
+    all: AllNodesIter<IdType, Ix>,
+    attached: Vec<bool>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> std::iter::Iterator for AttachedNodesIter<IdType, Ix> {
+    type Item = Node<IdType, Ix>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let attached = &self.attached;
+        self.all.by_ref().find(|n| attached.get(n.index()) == Some(&true))
+    }
+}
+
+pub struct DetachedRootsIter<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    all: AllNodesIter<IdType, Ix>,
+    implicit_root: Option<Node<IdType, Ix>>,
+    tree: &'a Tree<IdType, T, Ix, S>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for DetachedRootsIter<'a, IdType, T, Ix, S> {
+    type Item = Node<IdType, Ix>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (t, implicit_root) = (self.tree, self.implicit_root);
+        self.all.by_ref().find(|n| n.parent(t).is_none() && !n.is_root(t) && implicit_root != Some(*n))
+    }
+}
+
+pub struct SiblingIter<IdType: Copy + Eq, T, Ix: IndexType = u32, S: Storage<T, Ix> = InterleavedStorage<T, Ix>> {
+    next: Option<Node<IdType, Ix>>,
+    _marker: std::marker::PhantomData<(T, S)>,
 }
-impl<IdType: Copy + Eq, T> SiblingIter<IdType, T> {
-    fn new(next: Option<Node<IdType>>) -> Self {
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> SiblingIter<IdType, T, Ix, S> {
+    fn new(next: Option<Node<IdType, Ix>>) -> Self {
         Self {
             next,
             _marker: std::marker::PhantomData,
         }
     }
-    pub fn next_value(&mut self, t: &Tree<IdType, T>) -> Option<Node<IdType>> {
+    pub fn next_value(&mut self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> {
         if let Some(n) = self.next {
             self.next = n.next_sibling(t);
             Some(n)
@@ -461,18 +1266,18 @@ impl<IdType: Copy + Eq, T> SiblingIter<IdType, T> {
         }
     }
 }
-impl<IdType: Copy + Eq, T> ContextIterator<Tree<IdType, T>> for SiblingIter<IdType, T> {
-    type Item = Node<IdType>;
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> ContextIterator<Tree<IdType, T, Ix, S>> for SiblingIter<IdType, T, Ix, S> {
+    type Item = Node<IdType, Ix>;
 
-    fn next(&mut self, t: &Tree<IdType, T>) -> Option<Self::Item> { self.next_value(t) }
+    fn next(&mut self, t: &Tree<IdType, T, Ix, S>) -> Option<Self::Item> { self.next_value(t) }
 }
 
-pub struct ContextFreeSiblingIter<'a, IdType: Copy + Eq + 'a, T: 'a> {
-    next: Option<Node<IdType>>,
-    tree: &'a Tree<IdType, T>,
+pub struct ContextFreeSiblingIter<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    next: Option<Node<IdType, Ix>>,
+    tree: &'a Tree<IdType, T, Ix, S>,
 }
-impl<'a, IdType: Copy + Eq + 'a, T: 'a> std::iter::Iterator for ContextFreeSiblingIter<'a, IdType, T> {
-    type Item = Node<IdType>;
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for ContextFreeSiblingIter<'a, IdType, T, Ix, S> {
+    type Item = Node<IdType, Ix>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(n) = self.next {
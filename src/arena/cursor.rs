@@ -0,0 +1,132 @@
+use super::{IndexType, InterleavedStorage, Node, Storage, Tree};
+
+/// The position of a cursor, along with the root of the subtree that pre-order moves stay within.
+#[derive(Clone, Copy)]
+struct Position<IdType: Copy + Eq, Ix: IndexType> {
+    node: Node<IdType, Ix>,
+    root: Node<IdType, Ix>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> Position<IdType, Ix> {
+    fn new(node: Node<IdType, Ix>) -> Self { Position { node, root: node } }
+
+    /// Moves to the result of the step (if any). Returns true if the position moved. Moving away
+    /// from the root (to its parent or a sibling) takes the root along.
+    fn step(&mut self, next: Option<Node<IdType, Ix>>, may_leave_root: bool) -> bool {
+        if let Some(n) = next {
+            if may_leave_root && self.node == self.root {
+                self.root = n;
+            }
+            self.node = n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the node following the current node in pre-order, within the subtree of the root.
+    fn next_preorder<T, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>) -> Option<Node<IdType, Ix>> {
+        self.node.first_child(t).or_else(|| self.node.next_in_subtree(t, self.root))
+    }
+}
+
+/// Adds the navigation methods, which are the same for both cursors.
+macro_rules! cursor_navigation {
+    () => {
+        pub fn node(&self) -> Node<IdType, Ix> { self.pos.node }
+
+        /// Moves the cursor to the specified node, which also becomes the root of the subtree
+        /// that `goto_next_preorder` stays within.
+        pub fn reset(&mut self, node: Node<IdType, Ix>) {
+            node.validate(self.tree);
+            self.pos = Position::new(node);
+        }
+
+        pub fn goto_parent(&mut self) -> bool { self.pos.step(self.pos.node.parent(self.tree), true) }
+        pub fn goto_first_child(&mut self) -> bool { self.pos.step(self.pos.node.first_child(self.tree), false) }
+        pub fn goto_last_child(&mut self) -> bool { self.pos.step(self.pos.node.last_child(self.tree), false) }
+        pub fn goto_next_sibling(&mut self) -> bool { self.pos.step(self.pos.node.next_sibling(self.tree), true) }
+        pub fn goto_prev_sibling(&mut self) -> bool { self.pos.step(self.pos.node.prev_sibling(self.tree), true) }
+
+        /// Moves to the next node in pre-order: the first child, the next sibling or the next
+        /// sibling of the closest ancestor that has one (in that order). The cursor does not leave
+        /// the subtree of the node it was created at (or reset to), so it does not continue with
+        /// the siblings of that node, nor with the following roots of the tree. Moving the cursor
+        /// to the parent or a sibling of that node extends the walk to the new node's subtree.
+        pub fn goto_next_preorder(&mut self) -> bool {
+            let next = self.pos.next_preorder(self.tree);
+            self.pos.step(next, false)
+        }
+    };
+}
+
+/// A read-only cursor that holds a position within a tree. The navigation methods move the cursor
+/// and return true, or leave it in place and return false if there is no node in that direction.
+pub struct TreeCursor<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    tree: &'a Tree<IdType, T, Ix, S>,
+    pos: Position<IdType, Ix>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> TreeCursor<'a, IdType, T, Ix, S> {
+    pub fn new(tree: &'a Tree<IdType, T, Ix, S>, node: Node<IdType, Ix>) -> Self {
+        node.validate(tree);
+        TreeCursor { tree, pos: Position::new(node) }
+    }
+
+    pub fn tree(&self) -> &'a Tree<IdType, T, Ix, S> { self.tree }
+    pub fn value(&self) -> &'a T { self.pos.node.value(self.tree) }
+
+    cursor_navigation!();
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> Clone for TreeCursor<'a, IdType, T, Ix, S> {
+    fn clone(&self) -> Self { TreeCursor { tree: self.tree, pos: self.pos } }
+}
+
+/// A cursor that borrows the tree mutably, allowing the tree to be edited at the cursor position.
+/// Navigation works the same way as with `TreeCursor`.
+pub struct CursorMut<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    tree: &'a mut Tree<IdType, T, Ix, S>,
+    pos: Position<IdType, Ix>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> CursorMut<'a, IdType, T, Ix, S> {
+    pub fn new(tree: &'a mut Tree<IdType, T, Ix, S>, node: Node<IdType, Ix>) -> Self {
+        node.validate(tree);
+        CursorMut { tree, pos: Position::new(node) }
+    }
+
+    pub fn tree(&self) -> &Tree<IdType, T, Ix, S> { self.tree }
+    pub fn value(&self) -> &T { self.pos.node.value(self.tree) }
+    pub fn value_mut(&mut self) -> &mut T { self.pos.node.value_mut(self.tree) }
+
+    /// Returns a read-only cursor at the current position (borrowing this cursor).
+    pub fn as_cursor(&self) -> TreeCursor<'_, IdType, T, Ix, S> { TreeCursor { tree: self.tree, pos: self.pos } }
+
+    cursor_navigation!();
+
+    /// Inserts a new node immediately after the current node and returns it. The cursor does not
+    /// move. Panics if the current node neither has a parent nor is a root of the tree.
+    pub fn insert_after(&mut self, value: T) -> Node<IdType, Ix> { self.pos.node.insert_next_sibling_value(self.tree, value) }
+
+    /// Inserts a new node immediately before the current node and returns it. The cursor does not
+    /// move. Panics if the current node neither has a parent nor is a root of the tree.
+    pub fn insert_before(&mut self, value: T) -> Node<IdType, Ix> { self.pos.node.insert_prev_sibling_value(self.tree, value) }
+
+    /// Adds a new node after the last child of the current node and returns it. The cursor does
+    /// not move.
+    pub fn insert_child(&mut self, value: T) -> Node<IdType, Ix> { self.pos.node.append_child_value(self.tree, value) }
+
+    /// Removes the current node (along with its subtree) and moves the cursor to the node that
+    /// followed the removed subtree in pre-order (see `goto_next_preorder`). Returns false if there
+    /// is no such node, in which case the cursor moves to the parent of the removed node (or stays
+    /// on the removed node if it did not have a parent).
+    pub fn remove_and_advance(&mut self) -> bool {
+        let removed = self.pos.node;
+        let next = removed.next_in_subtree(self.tree, self.pos.root);
+        let parent = removed.parent(self.tree);
+        removed.remove(self.tree);
+        if next.is_some() {
+            self.pos.step(next, false)
+        } else {
+            self.pos.step(parent, true);
+            false
+        }
+    }
+}
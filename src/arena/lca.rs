@@ -0,0 +1,115 @@
+use super::{IndexType, Node, OrderIndex, Storage, Tree};
+
+/// Answers lowest common ancestor and ancestor queries for a subtree in constant time.
+///
+/// The index is built from a snapshot of the subtree (an Euler tour along with a sparse table of
+/// minimum depths, using O(n log n) memory). It is not updated when the tree changes, so it needs
+/// to be rebuilt after structural edits. Nodes that were not part of the subtree when the index was
+/// built are not found by any query.
+#[derive(Clone, Debug)]
+pub struct LcaIndex<IdType: Copy + Eq, Ix: IndexType = u32> {
+    order: OrderIndex<IdType, Ix>,
+    /// The nodes in the order they are visited by the Euler tour (parents are revisited between
+    /// children).
+    tour: Vec<Node<IdType, Ix>>,
+    /// The depth of each tour entry.
+    depths: Vec<usize>,
+    /// The first tour position of each node, indexed by the node index (only meaningful for
+    /// nodes contained in `order`).
+    first: Vec<usize>,
+    /// `sparse[k][i]` is the tour position with the minimum depth among positions `i..i + 2^k`.
+    sparse: Vec<Vec<usize>>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> LcaIndex<IdType, Ix> {
+    /// Builds the index for the subtree starting at `root`.
+    pub fn new<T, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Self {
+        let mut result = LcaIndex {
+            order: OrderIndex::new(t, root),
+            tour: Vec::new(),
+            depths: Vec::new(),
+            first: vec![0; t.nodes.len()],
+            sparse: Vec::new(),
+        };
+
+        let mut n = root;
+        let mut depth = 0;
+        result.enter(n, depth);
+        'tour: loop {
+            if let Some(c) = n.first_child(t) {
+                n = c;
+                depth += 1;
+                result.enter(n, depth);
+                continue;
+            }
+            // Return to the parents until one of them has another child to visit
+            while n != root {
+                let p = n.parent(t).unwrap();
+                depth -= 1;
+                result.visit(p, depth);
+                if let Some(s) = n.next_sibling(t) {
+                    n = s;
+                    depth += 1;
+                    result.enter(n, depth);
+                    continue 'tour;
+                }
+                n = p;
+            }
+            break;
+        }
+
+        let len = result.tour.len();
+        result.sparse.push((0..len).collect());
+        let mut width = 1;
+        while width * 2 <= len {
+            let prev = result.sparse.last().unwrap();
+            let row = (0..=len - width * 2).map(|i| result.shallower(prev[i], prev[i + width])).collect();
+            result.sparse.push(row);
+            width *= 2;
+        }
+        result
+    }
+
+    /// Adds the first visit of a node to the tour.
+    fn enter(&mut self, n: Node<IdType, Ix>, depth: usize) {
+        self.first[n.index()] = self.tour.len();
+        self.visit(n, depth);
+    }
+
+    fn visit(&mut self, n: Node<IdType, Ix>, depth: usize) {
+        self.tour.push(n);
+        self.depths.push(depth);
+    }
+
+    fn shallower(&self, a: usize, b: usize) -> usize { if self.depths[b] < self.depths[a] { b } else { a } }
+
+    /// Returns the first tour position of the node, or `None` if the node is not indexed.
+    fn position(&self, n: Node<IdType, Ix>) -> Option<usize> { if self.order.contains(n) { Some(self.first[n.index()]) } else { None } }
+
+    /// Returns the pre-order and post-order numbers used for ancestor tests.
+    pub fn order(&self) -> &OrderIndex<IdType, Ix> { &self.order }
+
+    /// Returns true if the node was part of the subtree when the index was built.
+    pub fn contains(&self, n: Node<IdType, Ix>) -> bool { self.order.contains(n) }
+
+    /// Returns the depth of the node relative to the root of the index.
+    pub fn depth(&self, n: Node<IdType, Ix>) -> Option<usize> { self.position(n).map(|p| self.depths[p]) }
+
+    /// Returns the deepest node that is an ancestor of (or equal to) both nodes.
+    pub fn lowest_common_ancestor(&self, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> Option<Node<IdType, Ix>> {
+        let (pa, pb) = (self.position(a)?, self.position(b)?);
+        let (l, r) = if pa <= pb { (pa, pb) } else { (pb, pa) };
+        let k = (usize::BITS - 1 - (r - l + 1).leading_zeros()) as usize;
+        let row = &self.sparse[k];
+        Some(self.tour[self.shallower(row[l], row[r + 1 - (1 << k)])])
+    }
+
+    /// Returns the number of edges on the path between the two nodes.
+    pub fn distance(&self, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> Option<usize> {
+        let lca = self.lowest_common_ancestor(a, b)?;
+        let dl = self.depth(lca).unwrap();
+        Some(self.depth(a).unwrap() - dl + self.depth(b).unwrap() - dl)
+    }
+
+    /// Returns true if `a` is a (strict) ancestor of `b`.
+    pub fn is_ancestor_of(&self, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> bool { self.order.is_ancestor_of(a, b) }
+}
@@ -0,0 +1,183 @@
+use super::{ContextFreeDepthFirstIter, ContextFreeSiblingIter, IndexType, InterleavedStorage, Node, Storage, Tree, TreeCursor};
+use std;
+
+/// A node along with a shared reference to its tree, so navigation does not require passing the
+/// tree to every call. Returned by `Tree::get`.
+pub struct NodeRef<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    tree: &'a Tree<IdType, T, Ix, S>,
+    node: Node<IdType, Ix>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> NodeRef<'a, IdType, T, Ix, S> {
+    pub fn new(tree: &'a Tree<IdType, T, Ix, S>, node: Node<IdType, Ix>) -> Self {
+        node.validate(tree);
+        NodeRef { tree, node }
+    }
+    fn wrap(&self, node: Option<Node<IdType, Ix>>) -> Option<Self> { node.map(|node| NodeRef { tree: self.tree, node }) }
+
+    pub fn tree(&self) -> &'a Tree<IdType, T, Ix, S> { self.tree }
+    pub fn node(&self) -> Node<IdType, Ix> { self.node }
+    pub fn value(&self) -> &'a T { self.node.value(self.tree) }
+
+    pub fn parent(&self) -> Option<Self> { self.wrap(self.node.parent(self.tree)) }
+    pub fn first_child(&self) -> Option<Self> { self.wrap(self.node.first_child(self.tree)) }
+    pub fn last_child(&self) -> Option<Self> { self.wrap(self.node.last_child(self.tree)) }
+    pub fn prev_sibling(&self) -> Option<Self> { self.wrap(self.node.prev_sibling(self.tree)) }
+    pub fn next_sibling(&self) -> Option<Self> { self.wrap(self.node.next_sibling(self.tree)) }
+    pub fn root(&self) -> Self {
+        NodeRef {
+            tree: self.tree,
+            node: self.node.root(self.tree),
+        }
+    }
+
+    pub fn children(&self) -> Children<'a, IdType, T, Ix, S> { Children { iter: self.node.children(self.tree) } }
+
+    /// Returns the ancestors of this node, starting with the parent and ending with the root.
+    pub fn ancestors(&self) -> Ancestors<'a, IdType, T, Ix, S> { Ancestors { next: self.parent() } }
+
+    /// Returns this node followed by all nodes underneath it (depth-first, pre-order).
+    pub fn descendants(&self) -> Descendants<'a, IdType, T, Ix, S> { Descendants { iter: self.node.depth_first_search_subtree(self.tree) } }
+
+    pub fn cursor(&self) -> TreeCursor<'a, IdType, T, Ix, S> { TreeCursor::new(self.tree, self.node) }
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> Clone for NodeRef<'a, IdType, T, Ix, S> {
+    fn clone(&self) -> Self { *self }
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> Copy for NodeRef<'a, IdType, T, Ix, S> {}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> PartialEq for NodeRef<'a, IdType, T, Ix, S> {
+    fn eq(&self, other: &Self) -> bool { self.node == other.node && std::ptr::eq(self.tree, other.tree) }
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> Eq for NodeRef<'a, IdType, T, Ix, S> {}
+impl<'a, IdType: Copy + Eq + std::fmt::Debug + 'a, T: std::fmt::Debug + 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::fmt::Debug for NodeRef<'a, IdType, T, Ix, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { f.debug_struct("NodeRef").field("node", &self.node).field("value", self.value()).finish() }
+}
+
+pub struct Children<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    iter: ContextFreeSiblingIter<'a, IdType, T, Ix, S>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for Children<'a, IdType, T, Ix, S> {
+    type Item = NodeRef<'a, IdType, T, Ix, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.iter.tree;
+        self.iter.next().map(|node| NodeRef { tree, node })
+    }
+}
+
+pub struct Ancestors<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    next: Option<NodeRef<'a, IdType, T, Ix, S>>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for Ancestors<'a, IdType, T, Ix, S> {
+    type Item = NodeRef<'a, IdType, T, Ix, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next;
+        self.next = result.and_then(|n| n.parent());
+        result
+    }
+}
+
+pub struct Descendants<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    iter: ContextFreeDepthFirstIter<'a, IdType, T, Ix, S>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> std::iter::Iterator for Descendants<'a, IdType, T, Ix, S> {
+    type Item = NodeRef<'a, IdType, T, Ix, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.iter.context;
+        self.iter.next().map(|(_, node)| NodeRef { tree, node })
+    }
+}
+
+/// A node along with a mutable reference to its tree, for editing the tree around the node.
+/// Returned by `Tree::get_mut`. Methods that add or find another node return a `NodeMut` that
+/// borrows this one.
+pub struct NodeMut<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType = u32, S: Storage<T, Ix> + 'a = InterleavedStorage<T, Ix>> {
+    tree: &'a mut Tree<IdType, T, Ix, S>,
+    node: Node<IdType, Ix>,
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> NodeMut<'a, IdType, T, Ix, S> {
+    pub fn new(tree: &'a mut Tree<IdType, T, Ix, S>, node: Node<IdType, Ix>) -> Self {
+        node.validate(tree);
+        NodeMut { tree, node }
+    }
+    fn wrap(&mut self, node: Option<Node<IdType, Ix>>) -> Option<NodeMut<'_, IdType, T, Ix, S>> { node.map(move |node| NodeMut { tree: self.tree, node }) }
+    fn at(&mut self, node: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> { NodeMut { tree: self.tree, node } }
+
+    pub fn tree(&mut self) -> &mut Tree<IdType, T, Ix, S> { self.tree }
+    pub fn node(&self) -> Node<IdType, Ix> { self.node }
+    pub fn value(&self) -> &T { self.node.value(self.tree) }
+    pub fn value_mut(&mut self) -> &mut T { self.node.value_mut(self.tree) }
+    pub fn into_value_mut(self) -> &'a mut T { self.node.value_mut(self.tree) }
+
+    pub fn as_ref(&self) -> NodeRef<'_, IdType, T, Ix, S> {
+        NodeRef {
+            tree: self.tree,
+            node: self.node,
+        }
+    }
+    pub fn into_ref(self) -> NodeRef<'a, IdType, T, Ix, S> {
+        NodeRef {
+            tree: self.tree,
+            node: self.node,
+        }
+    }
+
+    pub fn parent(&mut self) -> Option<NodeMut<'_, IdType, T, Ix, S>> {
+        let n = self.node.parent(self.tree);
+        self.wrap(n)
+    }
+    pub fn first_child(&mut self) -> Option<NodeMut<'_, IdType, T, Ix, S>> {
+        let n = self.node.first_child(self.tree);
+        self.wrap(n)
+    }
+    pub fn last_child(&mut self) -> Option<NodeMut<'_, IdType, T, Ix, S>> {
+        let n = self.node.last_child(self.tree);
+        self.wrap(n)
+    }
+    pub fn prev_sibling(&mut self) -> Option<NodeMut<'_, IdType, T, Ix, S>> {
+        let n = self.node.prev_sibling(self.tree);
+        self.wrap(n)
+    }
+    pub fn next_sibling(&mut self) -> Option<NodeMut<'_, IdType, T, Ix, S>> {
+        let n = self.node.next_sibling(self.tree);
+        self.wrap(n)
+    }
+
+    pub fn append_child(&mut self, child: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.append_child(self.tree, child);
+        self.at(n)
+    }
+    pub fn append_child_value(&mut self, child_value: T) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.append_child_value(self.tree, child_value);
+        self.at(n)
+    }
+    pub fn prepend_child(&mut self, child: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.prepend_child(self.tree, child);
+        self.at(n)
+    }
+    pub fn prepend_child_value(&mut self, child_value: T) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.prepend_child_value(self.tree, child_value);
+        self.at(n)
+    }
+    pub fn insert_next_sibling(&mut self, sibling: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.insert_next_sibling(self.tree, sibling);
+        self.at(n)
+    }
+    pub fn insert_next_sibling_value(&mut self, sibling_value: T) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.insert_next_sibling_value(self.tree, sibling_value);
+        self.at(n)
+    }
+    pub fn insert_prev_sibling(&mut self, sibling: Node<IdType, Ix>) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.insert_prev_sibling(self.tree, sibling);
+        self.at(n)
+    }
+    pub fn insert_prev_sibling_value(&mut self, sibling_value: T) -> NodeMut<'_, IdType, T, Ix, S> {
+        let n = self.node.insert_prev_sibling_value(self.tree, sibling_value);
+        self.at(n)
+    }
+
+    /// Detaches this node (along with its subtree) from its parent (or from the roots of the tree).
+    pub fn remove(&mut self) { self.node.remove(self.tree) }
+    pub fn remove_children(&mut self) { self.node.remove_children(self.tree) }
+}
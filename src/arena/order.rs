@@ -0,0 +1,90 @@
+use super::{IndexType, Node, Storage, Tree};
+use std;
+use std::cmp::Ordering;
+
+const NOT_INDEXED: usize = usize::MAX;
+
+/// Assigns pre-order and post-order numbers to the nodes of a subtree, so ancestor tests become
+/// interval checks and nodes can be compared (or sorted) in document order in constant time.
+///
+/// The numbers are taken from a snapshot of the subtree. The index is not updated when the tree
+/// changes, so it needs to be rebuilt after structural edits. Nodes that were not part of the
+/// subtree when the index was built are not found by any query.
+#[derive(Clone, Debug)]
+pub struct OrderIndex<IdType: Copy + Eq, Ix: IndexType = u32> {
+    tree_id: IdType,
+    /// The pre-order and post-order number of each node, indexed by the node index.
+    pre: Vec<usize>,
+    post: Vec<usize>,
+    _marker: std::marker::PhantomData<Ix>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> OrderIndex<IdType, Ix> {
+    /// Builds the index for the subtree starting at `root`.
+    pub fn new<T, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Self {
+        root.validate(t);
+        let mut result = OrderIndex {
+            tree_id: t.id,
+            pre: vec![NOT_INDEXED; t.nodes.len()],
+            post: vec![NOT_INDEXED; t.nodes.len()],
+            _marker: std::marker::PhantomData,
+        };
+        let (mut pre, mut post) = (0, 0);
+        let mut n = root;
+        result.pre[n.index()] = pre;
+        'walk: loop {
+            if let Some(c) = n.first_child(t) {
+                n = c;
+                pre += 1;
+                result.pre[n.index()] = pre;
+                continue;
+            }
+            // The node is finished, as are its ancestors up to the first one with another child
+            loop {
+                result.post[n.index()] = post;
+                post += 1;
+                if n == root {
+                    break 'walk;
+                }
+                if let Some(s) = n.next_sibling(t) {
+                    n = s;
+                    pre += 1;
+                    result.pre[n.index()] = pre;
+                    continue 'walk;
+                }
+                n = n.parent(t).unwrap();
+            }
+        }
+        result
+    }
+
+    fn number(&self, numbers: &[usize], n: Node<IdType, Ix>) -> Option<usize> {
+        if n.tree_id != self.tree_id {
+            return None;
+        }
+        numbers.get(n.index()).cloned().filter(|&v| v != NOT_INDEXED)
+    }
+
+    /// Returns true if the node was part of the subtree when the index was built.
+    pub fn contains(&self, n: Node<IdType, Ix>) -> bool { self.pre_order(n).is_some() }
+
+    /// Returns the position of the node in a pre-order walk of the subtree (the root is zero).
+    pub fn pre_order(&self, n: Node<IdType, Ix>) -> Option<usize> { self.number(&self.pre, n) }
+
+    /// Returns the position of the node in a post-order walk of the subtree (the root is last).
+    pub fn post_order(&self, n: Node<IdType, Ix>) -> Option<usize> { self.number(&self.post, n) }
+
+    /// Returns true if `a` is a (strict) ancestor of `b`.
+    pub fn is_ancestor_of(&self, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> bool {
+        match (self.pre_order(a), self.pre_order(b)) {
+            (Some(pa), Some(pb)) => pa < pb && self.post[b.index()] < self.post[a.index()],
+            _ => false,
+        }
+    }
+
+    /// Compares the nodes in document order, or returns `None` if one of them is not indexed.
+    pub fn compare(&self, a: Node<IdType, Ix>, b: Node<IdType, Ix>) -> Option<Ordering> { Some(self.pre_order(a)?.cmp(&self.pre_order(b)?)) }
+
+    /// Sorts the nodes in document order. Nodes that are not indexed are placed at the end (in
+    /// their original order).
+    pub fn sort(&self, nodes: &mut [Node<IdType, Ix>]) { nodes.sort_by_key(|&n| self.pre_order(n).unwrap_or(NOT_INDEXED)) }
+}
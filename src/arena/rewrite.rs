@@ -0,0 +1,303 @@
+//! Pattern-based rewriting of arena trees.
+//!
+//! A `Rewriter` holds a list of rules. Each rule has a `Pattern` that is matched against a node
+//! (its value and optionally the shape of its children) and a replacement closure that builds the
+//! replacement node from the captured nodes. The rules are applied until none of them matches
+//! anymore, or until the step limit is reached.
+//!
+//! ```
+//! use trees::arena::Tree;
+//! use trees::arena::rewrite::{Pattern, Rewriter, Rule};
+//!
+//! // Simplify `x + 0` to `x`
+//! let mut t: Tree<(), &str> = Tree::new(());
+//! let add = t.create_node("+");
+//! add.append_child_value(&mut t, "x");
+//! add.append_child_value(&mut t, "0");
+//!
+//! let rewriter = Rewriter::new().rule(Rule::new(
+//!     "add-zero",
+//!     Pattern::node(|v: &&str| *v == "+").with_children(vec![Pattern::capture("x"), Pattern::node(|v: &&str| *v == "0")]),
+//!     |_, c| Some(c["x"]),
+//! ));
+//! let result = rewriter.rewrite(&mut t, add).unwrap();
+//! assert_eq!(t[result.root()], "x");
+//! assert_eq!(result.trace().len(), 1);
+//! ```
+
+use super::{IndexType, InterleavedStorage, Node, Storage, Tree};
+use std;
+use std::collections::HashMap;
+
+type Predicate<T> = Box<dyn Fn(&T) -> bool>;
+
+/// Describes the nodes a rule applies to.
+pub struct Pattern<T> {
+    predicate: Option<Predicate<T>>,
+    capture: Option<String>,
+    children: Option<Vec<Pattern<T>>>,
+}
+impl<T> Pattern<T> {
+    /// Matches any node (regardless of its value and children).
+    pub fn any() -> Self {
+        Pattern {
+            predicate: None,
+            capture: None,
+            children: None,
+        }
+    }
+
+    /// Matches any node and captures it under the specified name.
+    pub fn capture(name: &str) -> Self { Pattern::any().bind(name) }
+
+    /// Matches nodes whose value satisfies the predicate (regardless of their children).
+    pub fn node<F: Fn(&T) -> bool + 'static>(predicate: F) -> Self {
+        Pattern {
+            predicate: Some(Box::new(predicate)),
+            capture: None,
+            children: None,
+        }
+    }
+
+    /// Also captures the matched node under the specified name.
+    pub fn bind(mut self, name: &str) -> Self {
+        self.capture = Some(name.to_string());
+        self
+    }
+
+    /// Requires the node to have exactly these children, each matching the corresponding pattern.
+    pub fn with_children(mut self, children: Vec<Pattern<T>>) -> Self {
+        self.children = Some(children);
+        self
+    }
+
+    /// Requires the node to have no children.
+    pub fn leaf(self) -> Self { self.with_children(Vec::new()) }
+
+    fn matches<IdType: Copy + Eq, Ix: IndexType, S: Storage<T, Ix>>(&self, t: &Tree<IdType, T, Ix, S>, n: Node<IdType, Ix>, captures: &mut HashMap<String, Node<IdType, Ix>>) -> bool {
+        if let Some(ref p) = self.predicate {
+            if !p(n.value(t)) {
+                return false;
+            }
+        }
+        if let Some(ref children) = self.children {
+            let mut actual = n.children(t);
+            for pattern in children {
+                match actual.next() {
+                    Some(c) if pattern.matches(t, c, captures) => {}
+                    _ => return false,
+                }
+            }
+            if actual.next().is_some() {
+                return false;
+            }
+        }
+        if let Some(ref name) = self.capture {
+            captures.insert(name.clone(), n);
+        }
+        true
+    }
+}
+
+/// The nodes captured by a pattern match.
+pub struct Captures<IdType: Copy + Eq, Ix: IndexType = u32> {
+    matched: Node<IdType, Ix>,
+    nodes: HashMap<String, Node<IdType, Ix>>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> Captures<IdType, Ix> {
+    /// Returns the node the pattern was matched against.
+    pub fn matched(&self) -> Node<IdType, Ix> { self.matched }
+    pub fn get(&self, name: &str) -> Option<Node<IdType, Ix>> { self.nodes.get(name).cloned() }
+}
+impl<IdType: Copy + Eq, Ix: IndexType> std::ops::Index<&str> for Captures<IdType, Ix> {
+    type Output = Node<IdType, Ix>;
+
+    fn index(&self, name: &str) -> &Node<IdType, Ix> { self.nodes.get(name).unwrap_or_else(|| panic!("No node was captured as '{}'", name)) }
+}
+
+type Replacement<IdType, T, Ix, S> = Box<dyn Fn(&mut Tree<IdType, T, Ix, S>, &Captures<IdType, Ix>) -> Option<Node<IdType, Ix>>>;
+
+/// A pattern along with its replacement.
+///
+/// The replacement closure receives the captures and returns the node that takes the place of
+/// the matched node. It may reuse captured nodes (they are moved into place), create new nodes, or
+/// edit the matched node in place and return it. Returning `None` declines the match, so the
+/// remaining rules are tried instead.
+pub struct Rule<IdType: Copy + Eq, T, Ix: IndexType = u32, S: Storage<T, Ix> = InterleavedStorage<T, Ix>> {
+    name: String,
+    pattern: Pattern<T>,
+    replacement: Replacement<IdType, T, Ix, S>,
+}
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> Rule<IdType, T, Ix, S> {
+    pub fn new<F>(name: &str, pattern: Pattern<T>, replacement: F) -> Self
+    where
+        F: Fn(&mut Tree<IdType, T, Ix, S>, &Captures<IdType, Ix>) -> Option<Node<IdType, Ix>> + 'static,
+    {
+        Rule {
+            name: name.to_string(),
+            pattern,
+            replacement: Box::new(replacement),
+        }
+    }
+
+    pub fn name(&self) -> &str { &self.name }
+}
+
+/// The order in which nodes are visited during each pass.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Strategy {
+    /// Children are rewritten before their parent (post-order).
+    BottomUp,
+    /// Parents are rewritten before their children (pre-order).
+    TopDown,
+}
+
+/// A rule application, as recorded in the trace.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RewriteStep<IdType: Copy + Eq, Ix: IndexType = u32> {
+    rule: String,
+    node: Node<IdType, Ix>,
+    replacement: Node<IdType, Ix>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> RewriteStep<IdType, Ix> {
+    /// Returns the name of the rule that was applied.
+    pub fn rule(&self) -> &str { &self.rule }
+    /// Returns the node that was matched.
+    pub fn node(&self) -> Node<IdType, Ix> { self.node }
+    /// Returns the node that took its place (which is the same node for in-place edits).
+    pub fn replacement(&self) -> Node<IdType, Ix> { self.replacement }
+}
+
+/// The result of a successful rewrite.
+#[derive(Clone, Debug)]
+pub struct Rewrite<IdType: Copy + Eq, Ix: IndexType = u32> {
+    root: Node<IdType, Ix>,
+    trace: Vec<RewriteStep<IdType, Ix>>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> Rewrite<IdType, Ix> {
+    /// Returns the root of the rewritten subtree (the original root may have been replaced).
+    pub fn root(&self) -> Node<IdType, Ix> { self.root }
+    pub fn trace(&self) -> &[RewriteStep<IdType, Ix>] { &self.trace }
+    pub fn into_trace(self) -> Vec<RewriteStep<IdType, Ix>> { self.trace }
+}
+
+/// Returned when the rules keep applying beyond the step limit. The tree is left in its state at
+/// that point.
+#[derive(Clone, Debug)]
+pub struct RewriteError<IdType: Copy + Eq, Ix: IndexType = u32> {
+    max_steps: usize,
+    partial: Rewrite<IdType, Ix>,
+}
+impl<IdType: Copy + Eq, Ix: IndexType> RewriteError<IdType, Ix> {
+    pub fn max_steps(&self) -> usize { self.max_steps }
+    /// Returns the root and trace at the point where rewriting stopped.
+    pub fn partial(&self) -> &Rewrite<IdType, Ix> { &self.partial }
+    pub fn into_partial(self) -> Rewrite<IdType, Ix> { self.partial }
+}
+impl<IdType: Copy + Eq, Ix: IndexType> std::fmt::Display for RewriteError<IdType, Ix> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "Rewriting did not reach a fixpoint within {} steps", self.max_steps) }
+}
+impl<IdType: Copy + Eq + std::fmt::Debug, Ix: IndexType> std::error::Error for RewriteError<IdType, Ix> {}
+
+/// Applies rules to a subtree until it no longer changes.
+///
+/// Each pass visits the nodes of the subtree in the order given by the strategy and applies the
+/// first matching rule to each node. Nodes created or moved during a pass are visited in the next
+/// pass. Rewriting stops after a pass without changes, or fails when a rule applies after
+/// `max_steps` rules have already been applied (10,000 by default).
+pub struct Rewriter<IdType: Copy + Eq, T, Ix: IndexType = u32, S: Storage<T, Ix> = InterleavedStorage<T, Ix>> {
+    rules: Vec<Rule<IdType, T, Ix, S>>,
+    strategy: Strategy,
+    max_steps: usize,
+}
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> Default for Rewriter<IdType, T, Ix, S> {
+    fn default() -> Self { Rewriter::new() }
+}
+impl<IdType: Copy + Eq, T, Ix: IndexType, S: Storage<T, Ix>> Rewriter<IdType, T, Ix, S> {
+    pub fn new() -> Self {
+        Rewriter {
+            rules: Vec::new(),
+            strategy: Strategy::BottomUp,
+            max_steps: 10_000,
+        }
+    }
+
+    /// Adds a rule. Rules are tried in the order they were added.
+    pub fn rule(mut self, rule: Rule<IdType, T, Ix, S>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Rewrites the subtree starting at `root` until no rule applies.
+    pub fn rewrite(&self, t: &mut Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Result<Rewrite<IdType, Ix>, RewriteError<IdType, Ix>> {
+        let mut result = Rewrite { root, trace: Vec::new() };
+        loop {
+            let mut changed = false;
+            for n in self.pass_order(t, result.root) {
+                // Skip nodes that were removed from the subtree earlier in this pass
+                if n != result.root && !result.root.is_ancestor_of(t, n) {
+                    continue;
+                }
+                if let Some((rule, replacement)) = self.apply(t, n) {
+                    if n == result.root {
+                        result.root = replacement;
+                    }
+                    result.trace.push(RewriteStep { rule, node: n, replacement });
+                    changed = true;
+                    if result.trace.len() > self.max_steps {
+                        return Err(RewriteError {
+                            max_steps: self.max_steps,
+                            partial: result,
+                        });
+                    }
+                }
+            }
+            if !changed {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn pass_order(&self, t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Vec<Node<IdType, Ix>> {
+        match self.strategy {
+            Strategy::TopDown => root.depth_first_search_subtree(t).map(|(_, n)| n).collect(),
+            Strategy::BottomUp => {
+                // Post-order: reverse the pre-order of the mirrored tree (last child first)
+                let mut result = Vec::new();
+                let mut stack = vec![root];
+                while let Some(n) = stack.pop() {
+                    result.push(n);
+                    stack.extend(n.children(t));
+                }
+                result.reverse();
+                result
+            }
+        }
+    }
+
+    /// Applies the first matching rule to the node, returning the rule name and the replacement.
+    fn apply(&self, t: &mut Tree<IdType, T, Ix, S>, n: Node<IdType, Ix>) -> Option<(String, Node<IdType, Ix>)> {
+        for rule in &self.rules {
+            let mut nodes = HashMap::new();
+            if !rule.pattern.matches(t, n, &mut nodes) {
+                continue;
+            }
+            let captures = Captures { matched: n, nodes };
+            if let Some(replacement) = (rule.replacement)(t, &captures) {
+                n.replace_with(t, replacement);
+                return Some((rule.name.clone(), replacement));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,91 @@
+use super::{IndexType, NodeLinks};
+
+/// Determines how the nodes of an arena tree are laid out in memory.
+///
+/// `InterleavedStorage` (the default) keeps the links of each node next to its value, which suits
+/// small values. `SplitStorage` keeps the links of all nodes in one vector and the values in
+/// another, so structural walks such as `depth_first_search` do not pull large values through the
+/// cache. Both layouts are used through the same `Tree` and `Node` API.
+///
+/// The trait is sealed, the layouts above are the only implementations.
+pub trait Storage<T, Ix: IndexType>: Default + sealed::Sealed {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool { self.len() == 0 }
+    /// Adds a node with the given value and no links.
+    fn push(&mut self, value: T);
+    /// Removes the nodes from the given index on.
+    fn truncate(&mut self, len: usize);
+    fn links(&self, idx: usize) -> &NodeLinks<Ix>;
+    fn links_mut(&mut self, idx: usize) -> &mut NodeLinks<Ix>;
+    fn value(&self, idx: usize) -> &T;
+    fn value_mut(&mut self, idx: usize) -> &mut T;
+    /// Returns mutable references to several values at once, or `None` if an index is repeated.
+    fn values_many_mut<const N: usize>(&mut self, idx: [usize; N]) -> Option<[&mut T; N]>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+#[derive(Clone)]
+struct NodeData<T, Ix: IndexType> {
+    value: T,
+    links: NodeLinks<Ix>,
+}
+
+/// Stores the links of each node next to its value.
+#[derive(Clone)]
+pub struct InterleavedStorage<T, Ix: IndexType> {
+    nodes: Vec<NodeData<T, Ix>>,
+}
+impl<T, Ix: IndexType> Default for InterleavedStorage<T, Ix> {
+    fn default() -> Self { InterleavedStorage { nodes: Vec::new() } }
+}
+impl<T, Ix: IndexType> sealed::Sealed for InterleavedStorage<T, Ix> {}
+impl<T, Ix: IndexType> Storage<T, Ix> for InterleavedStorage<T, Ix> {
+    fn len(&self) -> usize { self.nodes.len() }
+    fn push(&mut self, value: T) {
+        self.nodes.push(NodeData {
+            value,
+            links: NodeLinks::new(),
+        })
+    }
+    fn truncate(&mut self, len: usize) { self.nodes.truncate(len) }
+    fn links(&self, idx: usize) -> &NodeLinks<Ix> { &self.nodes[idx].links }
+    fn links_mut(&mut self, idx: usize) -> &mut NodeLinks<Ix> { &mut self.nodes[idx].links }
+    fn value(&self, idx: usize) -> &T { &self.nodes[idx].value }
+    fn value_mut(&mut self, idx: usize) -> &mut T { &mut self.nodes[idx].value }
+    fn values_many_mut<const N: usize>(&mut self, idx: [usize; N]) -> Option<[&mut T; N]> { self.nodes.get_disjoint_mut(idx).ok().map(|nodes| nodes.map(|n| &mut n.value)) }
+}
+
+/// Stores the links of all nodes in one vector and the values in another.
+#[derive(Clone)]
+pub struct SplitStorage<T, Ix: IndexType> {
+    links: Vec<NodeLinks<Ix>>,
+    values: Vec<T>,
+}
+impl<T, Ix: IndexType> Default for SplitStorage<T, Ix> {
+    fn default() -> Self {
+        SplitStorage {
+            links: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+impl<T, Ix: IndexType> sealed::Sealed for SplitStorage<T, Ix> {}
+impl<T, Ix: IndexType> Storage<T, Ix> for SplitStorage<T, Ix> {
+    fn len(&self) -> usize { self.values.len() }
+    fn push(&mut self, value: T) {
+        self.links.push(NodeLinks::new());
+        self.values.push(value);
+    }
+    fn truncate(&mut self, len: usize) {
+        self.links.truncate(len);
+        self.values.truncate(len);
+    }
+    fn links(&self, idx: usize) -> &NodeLinks<Ix> { &self.links[idx] }
+    fn links_mut(&mut self, idx: usize) -> &mut NodeLinks<Ix> { &mut self.links[idx] }
+    fn value(&self, idx: usize) -> &T { &self.values[idx] }
+    fn value_mut(&mut self, idx: usize) -> &mut T { &mut self.values[idx] }
+    fn values_many_mut<const N: usize>(&mut self, idx: [usize; N]) -> Option<[&mut T; N]> { self.values.get_disjoint_mut(idx).ok() }
+}
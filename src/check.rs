@@ -0,0 +1,86 @@
+use std;
+
+/// Describes a single broken link found by `arena::Tree::check_invariants` or
+/// `rctree::RcNode::check_subtree`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ViolationKind {
+    /// A link refers to a node that does not exist.
+    InvalidLink,
+    /// The node is listed among the children of a parent, but its parent link points elsewhere
+    /// (or, for a root of an arena tree, it has a parent).
+    WrongParent,
+    /// The node has a parent link, but it is not listed among the children of that parent.
+    NotListedByParent,
+    /// The previous sibling link does not point at the node that comes before it in its list.
+    WrongPrevSibling,
+    /// The last child link (or the last root of an arena tree) does not point at the end of the
+    /// list. The first child link is set without a last child link, or the other way around.
+    WrongLastChild,
+    /// The node is listed more than once, either under two parents or twice in the same list.
+    ListedTwice,
+    /// The node is its own ancestor.
+    Cycle,
+}
+impl std::fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let message = match *self {
+            ViolationKind::InvalidLink => "link refers to a node that does not exist",
+            ViolationKind::WrongParent => "parent link does not match the list that contains the node",
+            ViolationKind::NotListedByParent => "node is not listed among the children of its parent",
+            ViolationKind::WrongPrevSibling => "previous sibling link does not match the list order",
+            ViolationKind::WrongLastChild => "last child link does not point at the end of the list",
+            ViolationKind::ListedTwice => "node is listed more than once",
+            ViolationKind::Cycle => "node is its own ancestor",
+        };
+        f.write_str(message)
+    }
+}
+
+/// A broken link along with the node where it was found.
+#[derive(Clone, Debug)]
+pub struct Violation<N> {
+    node: N,
+    kind: ViolationKind,
+}
+impl<N> Violation<N> {
+    pub(crate) fn new(node: N, kind: ViolationKind) -> Self { Violation { node, kind } }
+    pub fn node(&self) -> &N { &self.node }
+    pub fn kind(&self) -> ViolationKind { self.kind }
+}
+impl<N: std::fmt::Debug> std::fmt::Display for Violation<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "{:?}: {}", self.node, self.kind) }
+}
+
+/// The result of an invariant check. Lists every broken link that was found (the check does not
+/// stop at the first one).
+#[derive(Clone, Debug)]
+pub struct InvariantReport<N> {
+    checked: usize,
+    violations: Vec<Violation<N>>,
+}
+impl<N> InvariantReport<N> {
+    pub(crate) fn new() -> Self {
+        InvariantReport {
+            checked: 0,
+            violations: Vec::new(),
+        }
+    }
+    pub(crate) fn checked_node(&mut self) { self.checked += 1 }
+    pub(crate) fn push(&mut self, node: N, kind: ViolationKind) { self.violations.push(Violation::new(node, kind)) }
+
+    /// Returns true if no violations were found.
+    pub fn is_ok(&self) -> bool { self.violations.is_empty() }
+    /// Returns the number of nodes that were checked.
+    pub fn checked(&self) -> usize { self.checked }
+    pub fn violations(&self) -> &[Violation<N>] { &self.violations }
+    pub fn into_violations(self) -> Vec<Violation<N>> { self.violations }
+}
+impl<N: std::fmt::Debug> std::fmt::Display for InvariantReport<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Checked {} nodes, found {} violations", self.checked, self.violations.len())?;
+        for v in &self.violations {
+            write!(f, "\n  {}", v)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! Graphviz DOT export, see `arena::Node::to_dot` and `RcNode::to_dot`.
+//!
+//! The nodes are numbered in pre-order (`n0` is the root of the exported subtree). Parent-child
+//! edges point from the parent to the child. Sibling-order edges (optional) are dashed and do not
+//! affect the layout ranks. A subtree can be drawn inside a box by selecting its root with
+//! `DotOptions::cluster`.
+
+use std::fmt::Write;
+use walk::WalkNode;
+
+type Label<'a, T> = Box<dyn Fn(&T) -> String + 'a>;
+type Attributes<'a, T> = Box<dyn Fn(&T) -> Vec<(&'static str, String)> + 'a>;
+type Cluster<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+
+/// Determines how the nodes of a tree are written to a DOT graph.
+pub struct DotOptions<'a, T> {
+    name: String,
+    label: Option<Label<'a, T>>,
+    attributes: Option<Attributes<'a, T>>,
+    cluster: Option<Cluster<'a, T>>,
+    sibling_edges: bool,
+}
+impl<'a, T> DotOptions<'a, T> {
+    /// Creates options that write the node ids as labels, without attributes, sibling edges or
+    /// clusters.
+    pub fn new() -> Self {
+        DotOptions {
+            name: "tree".into(),
+            label: None,
+            attributes: None,
+            cluster: None,
+            sibling_edges: false,
+        }
+    }
+
+    /// Sets the name of the graph (`tree` by default).
+    pub fn name<N: Into<String>>(mut self, name: N) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the label of each node.
+    pub fn label<F: Fn(&T) -> String + 'a>(mut self, f: F) -> Self {
+        self.label = Some(Box::new(f));
+        self
+    }
+
+    /// Sets additional attributes of each node, for example `("color", "red".into())`.
+    pub fn attributes<F: Fn(&T) -> Vec<(&'static str, String)> + 'a>(mut self, f: F) -> Self {
+        self.attributes = Some(Box::new(f));
+        self
+    }
+
+    /// Draws the subtree of every node for which the predicate returns true in its own cluster.
+    /// Clusters may be nested.
+    pub fn cluster<F: Fn(&T) -> bool + 'a>(mut self, f: F) -> Self {
+        self.cluster = Some(Box::new(f));
+        self
+    }
+
+    /// Adds an edge from each node to its next sibling.
+    pub fn sibling_edges(mut self, enabled: bool) -> Self {
+        self.sibling_edges = enabled;
+        self
+    }
+}
+impl<'a, T> Default for DotOptions<'a, T> {
+    fn default() -> Self { DotOptions::new() }
+}
+
+struct Frame<N> {
+    node: N,
+    id: usize,
+    clustered: bool,
+    /// The number of clusters that are open while writing the node (including its own).
+    clusters: usize,
+    /// The child that was written last, along with its id.
+    last_child: Option<(N, usize)>,
+}
+
+/// Writes the subtree of the node as a DOT graph.
+pub(crate) fn to_dot<N: WalkNode>(root: N, options: &DotOptions<N::Value>) -> String {
+    let mut out = String::new();
+    let mut edges = String::new();
+    let mut next_id = 0;
+    let mut stack = Vec::new();
+
+    // Note: Writing to a string cannot fail, so the results of write! are ignored
+    let _ = writeln!(out, "digraph {} {{", quote(&options.name));
+    enter(&mut out, options, &mut stack, &mut next_id, root);
+    while let Some(frame) = stack.last_mut() {
+        let next = match frame.last_child {
+            Some((ref c, _)) => c.next_sibling(),
+            None => frame.node.first_child(),
+        };
+        if let Some(c) = next {
+            let id = next_id;
+            let _ = writeln!(edges, "    n{} -> n{};", frame.id, id);
+            if let (true, Some((_, prev))) = (options.sibling_edges, frame.last_child.as_ref()) {
+                let _ = writeln!(edges, "    n{} -> n{} [style=dashed, constraint=false];", prev, id);
+            }
+            frame.last_child = Some((c.clone(), id));
+            enter(&mut out, options, &mut stack, &mut next_id, c);
+        } else {
+            let frame = stack.pop().unwrap();
+            if frame.clustered {
+                let _ = writeln!(out, "{}}}", indent(frame.clusters - 1));
+            }
+        }
+    }
+    out.push_str(&edges);
+    out.push_str("}\n");
+    out
+}
+
+/// Writes the declaration of the node (opening its cluster first, if any) and pushes its frame.
+fn enter<N: WalkNode>(out: &mut String, options: &DotOptions<N::Value>, stack: &mut Vec<Frame<N>>, next_id: &mut usize, node: N) {
+    let id = *next_id;
+    *next_id += 1;
+    let clustered = options.cluster.as_ref().is_some_and(|f| f(node.value()));
+    let outer = stack.last().map_or(0, |f| f.clusters);
+    if clustered {
+        let _ = writeln!(out, "{}subgraph cluster_{} {{", indent(outer), id);
+    }
+    let clusters = if clustered { outer + 1 } else { outer };
+
+    let mut attributes = Vec::new();
+    if let Some(ref f) = options.label {
+        attributes.push(format!("label={}", quote(&f(node.value()))));
+    }
+    if let Some(ref f) = options.attributes {
+        for (name, value) in f(node.value()) {
+            attributes.push(format!("{}={}", name, quote(&value)));
+        }
+    }
+    stack.push(Frame {
+        node,
+        id,
+        clustered,
+        clusters,
+        last_child: None,
+    });
+    let prefix = indent(clusters);
+    if attributes.is_empty() {
+        let _ = writeln!(out, "{}n{};", prefix, id);
+    } else {
+        let _ = writeln!(out, "{}n{} [{}];", prefix, id, attributes.join(", "));
+    }
+}
+
+/// Returns the indentation of a line, based on the number of open clusters.
+fn indent(clusters: usize) -> String { "    ".repeat(clusters + 1) }
+
+/// Writes the string as a quoted DOT identifier.
+fn quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
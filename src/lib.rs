@@ -1,6 +1,21 @@
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+
 pub mod arena;
+mod check;
 mod context_iter;
+pub mod dot;
+pub mod newick;
+mod path;
 pub mod rctree;
+pub mod select;
+pub mod sync;
+pub mod testing;
+mod walk;
 
 /*
 TODO Add the following:
@@ -9,4 +24,29 @@ withdelete - adds deleted flag, useful if the tree will have a lot of removals a
              include a created id to ensure deleted nodes cannot be referenced again?
 */
 
+pub use check::{InvariantReport, Violation, ViolationKind};
 pub use context_iter::ContextIterator;
+pub use path::{NodePath, ParseNodePathError};
+
+/// Determines what happens to the children of a node that is rejected by `retain_descendants`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum RetainMode {
+    /// The rejected node is removed along with its entire subtree.
+    RemoveSubtree,
+    /// The rejected node is removed and its children take its place (in order). The hoisted
+    /// children are then tested against the predicate as well.
+    HoistChildren,
+}
+
+/// Specifies where nodes are placed among the children of their new parent.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum InsertPosition<N> {
+    /// Before all existing children.
+    First,
+    /// After all existing children.
+    Last,
+    /// Immediately before the specified child.
+    Before(N),
+    /// Immediately after the specified child.
+    After(N),
+}
@@ -0,0 +1,326 @@
+//! Newick format import and export for phylogenetic trees.
+//!
+//! A file holds one or more trees, each terminated by `;`, for example `(A:0.1,B:0.2,(C,D)E)F;`.
+//! Every node may have a label and a branch length (after `:`). Labels can be quoted with single
+//! quotes (a quote inside a quoted label is written twice); underscores in unquoted labels stand
+//! for spaces. Comments in square brackets and whitespace between tokens are ignored. Branch
+//! lengths are read as `f64`, including `NaN` and `inf`.
+//!
+//! The node values are built from the label and branch length with `NewickValue`. `NewickNode` is
+//! a ready-made value type that simply stores both.
+
+use arena::{IndexType, Node, Storage, Tree};
+use rctree::RcNode;
+use std;
+use walk::WalkNode;
+
+/// A node value that can be read from and written to Newick.
+pub trait NewickValue {
+    fn from_newick(label: Option<String>, branch_length: Option<f64>) -> Self;
+    fn label(&self) -> Option<&str>;
+    fn branch_length(&self) -> Option<f64>;
+}
+
+/// Stores the label and branch length of a node.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct NewickNode {
+    pub label: Option<String>,
+    pub branch_length: Option<f64>,
+}
+impl NewickValue for NewickNode {
+    fn from_newick(label: Option<String>, branch_length: Option<f64>) -> Self { NewickNode { label, branch_length } }
+    fn label(&self) -> Option<&str> { self.label.as_deref() }
+    fn branch_length(&self) -> Option<f64> { self.branch_length }
+}
+
+/// Returned when the input is not valid Newick.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct NewickError {
+    line: usize,
+    column: usize,
+    message: &'static str,
+}
+impl NewickError {
+    /// Returns the line of the problem (starting at 1).
+    pub fn line(&self) -> usize { self.line }
+    /// Returns the column of the problem, counted in characters (starting at 1).
+    pub fn column(&self) -> usize { self.column }
+    pub fn message(&self) -> &str { self.message }
+}
+impl std::fmt::Display for NewickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "Invalid Newick input at line {}, column {}: {}", self.line, self.column, self.message) }
+}
+impl std::error::Error for NewickError {}
+
+/// Parses every tree of the input into the arena. The root of each tree is added to the roots of
+/// the arena (see `Tree::push_root`) and returned. If the input is invalid, the nodes created
+/// during the parse are removed again, so the arena is left unchanged.
+pub fn parse_arena<IdType: Copy + Eq, T: NewickValue, Ix: IndexType, S: Storage<T, Ix>>(input: &str, t: &mut Tree<IdType, T, Ix, S>) -> Result<Vec<Node<IdType, Ix>>, NewickError> {
+    let len = t.node_count();
+    // Note: The parsed nodes are only linked among themselves until the roots are pushed below
+    let roots = Parser::new(input).parse_all(&mut ArenaBuilder(t)).inspect_err(|_| t.truncate(len))?;
+    for &r in &roots {
+        t.push_root(r);
+    }
+    Ok(roots)
+}
+
+/// Parses every tree of the input and returns their roots.
+pub fn parse_rc<T: NewickValue>(input: &str) -> Result<Vec<RcNode<T>>, NewickError> { Parser::new(input).parse_all(&mut RcBuilder) }
+
+/// Writes the subtree of the node as a single Newick tree (terminated by `;`).
+pub fn write_arena<IdType: Copy + Eq, T: NewickValue, Ix: IndexType, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> String { write(t.get(root)) }
+
+/// Writes the subtree of the node as a single Newick tree (terminated by `;`).
+pub fn write_rc<T: NewickValue>(root: &RcNode<T>) -> String { write(root.clone()) }
+
+/// Creates the nodes of the parsed trees. Children are complete before their parent is created,
+/// since the label of a node follows its children.
+trait Builder<T> {
+    type Node;
+
+    fn create(&mut self, value: T) -> Self::Node;
+    fn append(&mut self, parent: &Self::Node, child: Self::Node);
+}
+
+struct ArenaBuilder<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType + 'a, S: Storage<T, Ix> + 'a>(&'a mut Tree<IdType, T, Ix, S>);
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType + 'a, S: Storage<T, Ix> + 'a> Builder<T> for ArenaBuilder<'a, IdType, T, Ix, S> {
+    type Node = Node<IdType, Ix>;
+
+    fn create(&mut self, value: T) -> Self::Node { self.0.create_node(value) }
+    fn append(&mut self, parent: &Self::Node, child: Self::Node) {
+        parent.append_child(self.0, child);
+    }
+}
+
+struct RcBuilder;
+impl<T> Builder<T> for RcBuilder {
+    type Node = RcNode<T>;
+
+    fn create(&mut self, value: T) -> Self::Node { RcNode::new(value) }
+    fn append(&mut self, parent: &Self::Node, child: Self::Node) { parent.append_child(&child) }
+}
+
+/// A line and column (starting at 1).
+type Position = (usize, usize);
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser {
+            input,
+            pos: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> { self.input[self.pos..].chars().next() }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &'static str) -> NewickError { self.error_at((self.line, self.column), message) }
+
+    fn error_at(&self, (line, column): Position, message: &'static str) -> NewickError { NewickError { line, column, message } }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), NewickError> {
+        while let Some(c) = self.peek() {
+            if c == '[' {
+                let start = (self.line, self.column);
+                while self.bump() != Some(']') {
+                    if self.peek().is_none() {
+                        return Err(self.error_at(start, "unterminated comment"));
+                    }
+                }
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_all<T: NewickValue, B: Builder<T>>(&mut self, b: &mut B) -> Result<Vec<B::Node>, NewickError> {
+        let mut trees = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek().is_none() {
+                return Ok(trees);
+            }
+            trees.push(self.parse_tree(b)?);
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(';') => {
+                    self.bump();
+                }
+                Some(')') => return Err(self.error("unmatched ')'")),
+                Some(_) => return Err(self.error("expected ';'")),
+                None => return Err(self.error("unexpected end of input, expected ';'")),
+            }
+        }
+    }
+
+    /// Parses a single tree (without the terminating `;`) and returns its root.
+    fn parse_tree<T: NewickValue, B: Builder<T>>(&mut self, b: &mut B) -> Result<B::Node, NewickError> {
+        // The children parsed so far for each open parenthesis, along with its position
+        let mut open: Vec<(Vec<B::Node>, Position)> = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some('(') {
+                open.push((Vec::new(), (self.line, self.column)));
+                self.bump();
+                continue;
+            }
+
+            // A leaf, followed by the nodes it completes (the parents closed right after it)
+            let mut node = self.parse_node(b, Vec::new())?;
+            loop {
+                self.skip_trivia()?;
+                if open.is_empty() {
+                    return Ok(node);
+                }
+                match self.peek() {
+                    Some(',') => {
+                        self.bump();
+                        open.last_mut().unwrap().0.push(node);
+                        break;
+                    }
+                    Some(')') => {
+                        self.bump();
+                        let (mut children, _) = open.pop().unwrap();
+                        children.push(node);
+                        node = self.parse_node(b, children)?;
+                    }
+                    Some(_) => return Err(self.error("expected ',' or ')'")),
+                    None => return Err(self.error_at(open.last().unwrap().1, "unmatched '('")),
+                }
+            }
+        }
+    }
+
+    /// Parses the label and branch length of a node and creates it along with its children.
+    fn parse_node<T: NewickValue, B: Builder<T>>(&mut self, b: &mut B, children: Vec<B::Node>) -> Result<B::Node, NewickError> {
+        self.skip_trivia()?;
+        let label = self.parse_label()?;
+        self.skip_trivia()?;
+        let mut branch_length = None;
+        if self.peek() == Some(':') {
+            self.bump();
+            self.skip_trivia()?;
+            let start = (self.line, self.column);
+            let begin = self.pos;
+            // Note: The whole token is parsed, which also accepts the NaN and infinite values written
+            // by `write_value`
+            while self.peek().is_some_and(|c| !c.is_whitespace() && !is_special(c)) {
+                self.bump();
+            }
+            let text = &self.input[begin..self.pos];
+            if text.is_empty() {
+                return Err(self.error("expected a branch length"));
+            }
+            branch_length = Some(text.parse().map_err(|_| self.error_at(start, "invalid branch length"))?);
+        }
+
+        let node = b.create(T::from_newick(label, branch_length));
+        for c in children {
+            b.append(&node, c);
+        }
+        Ok(node)
+    }
+
+    fn parse_label(&mut self) -> Result<Option<String>, NewickError> {
+        if self.peek() == Some('\'') {
+            let start = (self.line, self.column);
+            self.bump();
+            let mut label = String::new();
+            loop {
+                match self.bump() {
+                    Some('\'') if self.peek() == Some('\'') => {
+                        self.bump();
+                        label.push('\'');
+                    }
+                    Some('\'') => return Ok(Some(label)),
+                    Some(c) => label.push(c),
+                    None => return Err(self.error_at(start, "unterminated quoted label")),
+                }
+            }
+        }
+
+        let mut label = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || is_special(c) {
+                break;
+            }
+            self.bump();
+            label.push(if c == '_' { ' ' } else { c });
+        }
+        Ok(if label.is_empty() { None } else { Some(label) })
+    }
+}
+
+/// Returns true for the characters that end an unquoted label.
+fn is_special(c: char) -> bool { "()[]':;,".contains(c) }
+
+fn write<N: WalkNode>(root: N) -> String
+where
+    N::Value: NewickValue,
+{
+    let mut out = String::new();
+    // Note: The subtree is walked using the links (instead of recursion) to support deep trees
+    let mut parents = Vec::new();
+    let mut cur = root;
+    'down: loop {
+        while let Some(c) = cur.first_child() {
+            out.push('(');
+            parents.push(cur);
+            cur = c;
+        }
+        loop {
+            write_value(&mut out, cur.value());
+            if parents.is_empty() {
+                out.push(';');
+                return out;
+            }
+            if let Some(s) = cur.next_sibling() {
+                out.push(',');
+                cur = s;
+                continue 'down;
+            }
+            out.push(')');
+            cur = parents.pop().unwrap();
+        }
+    }
+}
+
+fn write_value<T: NewickValue>(out: &mut String, value: &T) {
+    if let Some(label) = value.label() {
+        if !label.is_empty() && label.chars().all(|c| !c.is_whitespace() && !is_special(c) && c != '_') {
+            out.push_str(label);
+        } else {
+            out.push('\'');
+            out.push_str(&label.replace('\'', "''"));
+            out.push('\'');
+        }
+    }
+    if let Some(length) = value.branch_length() {
+        out.push(':');
+        out.push_str(&length.to_string());
+    }
+}
@@ -0,0 +1,101 @@
+use std;
+
+/// The address of a node relative to a root, given as the child index to follow at each level.
+/// The root itself has an empty path.
+///
+/// Paths are ordered in document order (pre-order): an ancestor comes before its descendants and
+/// earlier siblings come before later ones. They are written as `/0/3/1` (the root is `/`), see
+/// `Display` and `FromStr`.
+#[derive(Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct NodePath(Vec<usize>);
+impl NodePath {
+    /// Returns the path of the root node.
+    pub fn root() -> Self { NodePath(Vec::new()) }
+
+    pub fn indices(&self) -> &[usize] { &self.0 }
+    pub fn into_indices(self) -> Vec<usize> { self.0 }
+    /// Returns the number of steps from the root (the depth of the node).
+    pub fn len(&self) -> usize { self.0.len() }
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+    pub fn is_root(&self) -> bool { self.0.is_empty() }
+
+    pub fn push(&mut self, child_index: usize) { self.0.push(child_index) }
+    pub fn pop(&mut self) -> Option<usize> { self.0.pop() }
+
+    /// Returns the path of the parent, or `None` for the root path.
+    pub fn parent(&self) -> Option<NodePath> {
+        if self.0.is_empty() {
+            None
+        } else {
+            Some(NodePath(self.0[..self.0.len() - 1].to_vec()))
+        }
+    }
+    pub fn child(&self, child_index: usize) -> NodePath {
+        let mut result = self.clone();
+        result.push(child_index);
+        result
+    }
+
+    /// Returns true if the other path points underneath this one (a path is not its own ancestor).
+    pub fn is_ancestor_of(&self, other: &NodePath) -> bool { self.0.len() < other.0.len() && other.0.starts_with(&self.0) }
+}
+impl From<Vec<usize>> for NodePath {
+    fn from(indices: Vec<usize>) -> Self { NodePath(indices) }
+}
+impl std::fmt::Display for NodePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for idx in &self.0 {
+            write!(f, "/{}", idx)?;
+        }
+        Ok(())
+    }
+}
+impl std::str::FromStr for NodePath {
+    type Err = ParseNodePathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('/') {
+            return Err(ParseNodePathError {
+                position: 0,
+                message: "path must start with '/'",
+            });
+        }
+        if s == "/" {
+            return Ok(NodePath::root());
+        }
+        let mut indices = Vec::new();
+        let mut position = 1;
+        for segment in s[1..].split('/') {
+            match segment.parse() {
+                Ok(idx) if segment.bytes().all(|b| b.is_ascii_digit()) => indices.push(idx),
+                _ => {
+                    return Err(ParseNodePathError {
+                        position,
+                        message: if segment.is_empty() { "empty path segment" } else { "path segment is not a child index" },
+                    })
+                }
+            }
+            position += segment.len() + 1;
+        }
+        Ok(NodePath(indices))
+    }
+}
+
+/// Returned when a string cannot be parsed as a `NodePath`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseNodePathError {
+    position: usize,
+    message: &'static str,
+}
+impl ParseNodePathError {
+    /// Returns the byte offset of the problem within the parsed string.
+    pub fn position(&self) -> usize { self.position }
+    pub fn message(&self) -> &str { self.message }
+}
+impl std::fmt::Display for ParseNodePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "Invalid node path at position {}: {}", self.position, self.message) }
+}
+impl std::error::Error for ParseNodePathError {}
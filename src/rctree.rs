@@ -1,19 +1,34 @@
+use dot::{self, DotOptions};
 use std;
-use std::cell::Cell;
+use std::borrow::Borrow;
+use std::cell::{BorrowError, BorrowMutError, Cell, Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
 use std::rc::{Rc, Weak};
+use walk::Links;
+use {InsertPosition, InvariantReport, NodePath, RetainMode, ViolationKind};
+
+// Note: The links are only accessed by temporarily taking the value out of the cell. Cloning an
+// Rc/Weak never runs user code, so the cell cannot be accessed while its value is taken.
+fn with_cell<L: Default, R, F: FnOnce(&L) -> R>(cell: &Cell<L>, f: F) -> R {
+    let d = cell.take();
+    let result = f(&d);
+    cell.set(d);
+    result
+}
 
 struct WeakLink<Value>(Cell<Option<Weak<NodeData<Value>>>>);
 impl<Value> WeakLink<Value> {
     pub fn new() -> Self { WeakLink(Cell::new(None)) }
-    pub fn is_some(&self) -> bool { unsafe { (*self.0.as_ptr()).is_some() } }
-    pub fn get(&self) -> Option<RcNode<Value>> { unsafe { (*self.0.as_ptr()).clone().and_then(|d| d.upgrade()).map(|d| RcNode(d)) } }
+    pub fn is_some(&self) -> bool { with_cell(&self.0, |d| d.is_some()) }
+    pub fn get(&self) -> Option<RcNode<Value>> { with_cell(&self.0, |d| d.as_ref().and_then(|d| d.upgrade()).map(RcNode)) }
     pub fn set(&self, val: Option<&RcNode<Value>>) { self.0.set(val.map(|n| Rc::downgrade(&n.0))) }
 }
 
 struct StrongLink<Value>(Cell<Option<Rc<NodeData<Value>>>>);
 impl<Value> StrongLink<Value> {
     pub fn new() -> Self { StrongLink(Cell::new(None)) }
-    pub fn get(&self) -> Option<RcNode<Value>> { unsafe { (*self.0.as_ptr()).clone().map(|d| RcNode(d)) } }
+    pub fn take(&self) -> Option<Rc<NodeData<Value>>> { self.0.take() }
+    pub fn get(&self) -> Option<RcNode<Value>> { with_cell(&self.0, |d| d.clone().map(RcNode)) }
     pub fn set(&self, val: Option<&RcNode<Value>>) { self.0.set(val.map(|n| n.0.clone())) }
 }
 
@@ -25,6 +40,26 @@ struct NodeData<Value> {
     last_child: StrongLink<Value>,
     value: Value,
 }
+impl<Value> NodeData<Value> {
+    fn take_strong_links(&self, pending: &mut Vec<Rc<NodeData<Value>>>) {
+        pending.extend(self.last_child.take());
+        pending.extend(self.first_child.take());
+        pending.extend(self.next_sibling.take());
+    }
+}
+impl<Value> std::ops::Drop for NodeData<Value> {
+    fn drop(&mut self) {
+        // Letting the strong links drop on their own would recurse once per child and sibling,
+        // so nodes that are only owned by these links are unlinked and dropped one at a time.
+        let mut pending = Vec::new();
+        self.take_strong_links(&mut pending);
+        while let Some(d) = pending.pop() {
+            if let Ok(d) = Rc::try_unwrap(d) {
+                d.take_strong_links(&mut pending);
+            }
+        }
+    }
+}
 
 pub struct RcNode<Value>(Rc<NodeData<Value>>);
 impl<Value> RcNode<Value> {
@@ -35,12 +70,25 @@ impl<Value> RcNode<Value> {
             prev_sibling: WeakLink::new(),
             first_child: StrongLink::new(),
             last_child: StrongLink::new(),
-            value: value,
+            value,
         };
         RcNode(Rc::new(d))
     }
 
-    fn ptr_eq(&self, other: &Self) -> bool { Rc::ptr_eq(&self.0, &other.0) }
+    /// Returns true if both handles refer to the same node.
+    pub fn ptr_eq(&self, other: &Self) -> bool { Rc::ptr_eq(&self.0, &other.0) }
+
+    /// Creates a weak handle to this node. The weak handle does not keep the node (or its
+    /// subtree) alive.
+    pub fn downgrade(&self) -> WeakRcNode<Value> { WeakRcNode(Rc::downgrade(&self.0)) }
+
+    /// Returns the number of strong references to this node. Note that this includes the internal
+    /// references held by the parent (first/last child) and previous sibling (next sibling).
+    pub fn strong_count(&self) -> usize { Rc::strong_count(&self.0) }
+
+    /// Returns the number of weak references to this node. Note that this includes the internal
+    /// references held by the children (parent) and next sibling (previous sibling).
+    pub fn weak_count(&self) -> usize { Rc::weak_count(&self.0) }
 
     pub fn parent(&self) -> Option<RcNode<Value>> { self.0.parent.get() }
     pub fn next_sibling(&self) -> Option<RcNode<Value>> { self.0.next_sibling.get() }
@@ -50,6 +98,112 @@ impl<Value> RcNode<Value> {
     pub fn value(&self) -> &Value { &self.0.value }
     pub fn children(&self) -> RcNodeSiblingIter<Value> { RcNodeSiblingIter { next: self.first_child() } }
 
+    /// Writes the subtree of this node as a Graphviz DOT graph.
+    pub fn to_dot(&self, options: &DotOptions<Value>) -> String { dot::to_dot(self.clone(), options) }
+
+    /// Returns the path from the root of this node to this node.
+    pub fn path(&self) -> NodePath {
+        let mut indices = Vec::new();
+        let mut cur = self.clone();
+        while let Some(p) = cur.parent() {
+            let mut idx = 0;
+            let mut sib = cur;
+            while let Some(s) = sib.prev_sibling() {
+                idx += 1;
+                sib = s;
+            }
+            indices.push(idx);
+            cur = p;
+        }
+        indices.reverse();
+        NodePath::from(indices)
+    }
+
+    /// Compares the positions of the nodes in document order (pre-order): ancestors come before
+    /// their descendants and earlier siblings (along with their subtrees) before later ones. Nodes
+    /// that are not connected are ordered by their roots in an unspecified but consistent way.
+    pub fn compare_document_order(a: &Self, b: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        if a.ptr_eq(b) {
+            return Ordering::Equal;
+        }
+        let chain = |n: &Self| {
+            let mut result = vec![n.clone()];
+            while let Some(p) = result.last().unwrap().parent() {
+                result.push(p);
+            }
+            result.reverse();
+            result
+        };
+        let (ca, cb) = (chain(a), chain(b));
+        let common = ca.iter().zip(&cb).take_while(|&(x, y)| x.ptr_eq(y)).count();
+        if common == ca.len() {
+            // a is an ancestor of b
+            return Ordering::Less;
+        } else if common == cb.len() {
+            return Ordering::Greater;
+        }
+        // Walk forward from both diverging nodes, whichever reaches the other one comes first
+        let (sa, sb) = (&ca[common], &cb[common]);
+        let (mut x, mut y) = (Some(sa.clone()), Some(sb.clone()));
+        while x.is_some() || y.is_some() {
+            x = x.and_then(|n| n.next_sibling());
+            y = y.and_then(|n| n.next_sibling());
+            if x.as_ref().is_some_and(|x| x.ptr_eq(sb)) {
+                return Ordering::Less;
+            } else if y.as_ref().is_some_and(|y| y.ptr_eq(sa)) {
+                return Ordering::Greater;
+            }
+        }
+        Rc::as_ptr(&sa.0).cmp(&Rc::as_ptr(&sb.0))
+    }
+
+    /// Follows the keys downward from this node, at each level picking the first child whose key
+    /// (as returned by `key_fn`) matches. Returns `None` if a key is not found.
+    pub fn find_path<K: ?Sized + Eq, I, F>(&self, keys: I, key_fn: F) -> Option<RcNode<Value>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+        F: Fn(&Value) -> &K,
+    {
+        let mut cur = self.clone();
+        for key in keys {
+            cur = cur.children().find(|c| key_fn(c.value()) == key.borrow())?;
+        }
+        Some(cur)
+    }
+
+    /// Same as `find_path`, except that missing nodes are created (as the last child) with the value
+    /// returned by `make_value` for the missing key. Returns the node for the last key.
+    pub fn get_or_create_path<K: ?Sized + Eq, I, F, M>(&self, keys: I, key_fn: F, mut make_value: M) -> RcNode<Value>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+        F: Fn(&Value) -> &K,
+        M: FnMut(&K) -> Value,
+    {
+        let mut cur = self.clone();
+        for key in keys {
+            let key = key.borrow();
+            let found = cur.children().find(|c| key_fn(c.value()) == key);
+            cur = match found {
+                Some(c) => c,
+                None => cur.append_child_value(make_value(key)),
+            };
+        }
+        cur
+    }
+
+    /// Follows the path starting at this node. Returns `None` if a child index along the path is
+    /// out of range.
+    pub fn resolve(&self, path: &NodePath) -> Option<RcNode<Value>> {
+        let mut cur = self.clone();
+        for &idx in path.indices() {
+            cur = cur.children().nth(idx)?;
+        }
+        Some(cur)
+    }
+
     /// Add the specified child to this node after the last existing child (if any).
     /// If the node already exists in a differnt tree/location, then it is removed from the old location and added to this one.
     pub fn append_child(&self, child: &Self) {
@@ -75,6 +229,309 @@ impl<Value> RcNode<Value> {
             parent.0.first_child.set(Some(child));
             parent.0.last_child.set(Some(child));
         }
+        parent.debug_check("append_child");
+    }
+
+    /// Add the specified child to this node before the first existing child (if any).
+    /// If the node already exists in a differnt tree/location, then it is removed from the old location and added to this one.
+    pub fn prepend_child(&self, child: &Self) {
+        if child.0.parent.is_some() {
+            child.remove();
+        }
+        let parent = self;
+        child.0.parent.set(Some(parent));
+        if let Some(fc) = parent.0.first_child.get() {
+            parent.0.first_child.set(Some(child));
+            child.0.next_sibling.set(Some(&fc));
+            fc.0.prev_sibling.set(Some(child));
+        } else {
+            parent.0.first_child.set(Some(child));
+            parent.0.last_child.set(Some(child));
+        }
+        parent.debug_check("prepend_child");
+    }
+    pub fn prepend_child_value(&self, value: Value) -> RcNode<Value> {
+        let child = RcNode::new(value);
+        self.prepend_child(&child);
+        child
+    }
+
+    /// Inserts the specified node immediately after this node. If this node does not have a
+    /// parent, then this method will panic.
+    pub fn insert_next_sibling(&self, sibling: &Self) {
+        let parent = self.parent().expect("This node must have a parent node for insert_next_sibling to work.");
+        if sibling.ptr_eq(self) {
+            return;
+        }
+        if sibling.0.parent.is_some() {
+            sibling.remove();
+        }
+        if let Some(next) = self.next_sibling() {
+            sibling.0.parent.set(Some(&parent));
+            sibling.0.prev_sibling.set(Some(self));
+            sibling.0.next_sibling.set(Some(&next));
+            self.0.next_sibling.set(Some(sibling));
+            next.0.prev_sibling.set(Some(sibling));
+        } else {
+            // Current node is the last one in the list, this is the same as calling append_child on the parent
+            parent.unchecked_append_child(sibling);
+        }
+        parent.debug_check("insert_next_sibling");
+    }
+    pub fn insert_next_sibling_value(&self, value: Value) -> RcNode<Value> {
+        let sibling = RcNode::new(value);
+        self.insert_next_sibling(&sibling);
+        sibling
+    }
+
+    /// Inserts the specified node immediately before this node. If this node does not have a
+    /// parent, then this method will panic.
+    pub fn insert_prev_sibling(&self, sibling: &Self) {
+        let parent = self.parent().expect("This node must have a parent node for insert_prev_sibling to work.");
+        if sibling.ptr_eq(self) {
+            return;
+        }
+        if sibling.0.parent.is_some() {
+            sibling.remove();
+        }
+        if let Some(prev) = self.prev_sibling() {
+            sibling.0.parent.set(Some(&parent));
+            sibling.0.prev_sibling.set(Some(&prev));
+            sibling.0.next_sibling.set(Some(self));
+            prev.0.next_sibling.set(Some(sibling));
+            self.0.prev_sibling.set(Some(sibling));
+        } else {
+            // Current node is the first one in the list, this is the same as calling prepend_child on the parent
+            parent.prepend_child(sibling);
+        }
+        parent.debug_check("insert_prev_sibling");
+    }
+    pub fn insert_prev_sibling_value(&self, value: Value) -> RcNode<Value> {
+        let sibling = RcNode::new(value);
+        self.insert_prev_sibling(&sibling);
+        sibling
+    }
+
+    /// Replaces this node with its children (in order). This node is left detached without any
+    /// children. If this node does not have a parent, then the children become detached roots.
+    pub fn unwrap(&self) {
+        let parent = self.parent();
+        while let Some(c) = self.first_child() {
+            if parent.is_some() {
+                self.insert_prev_sibling(&c);
+            } else {
+                c.remove();
+            }
+        }
+        self.remove();
+        if let Some(p) = parent {
+            p.debug_check("unwrap");
+        }
+    }
+
+    /// Inserts the new parent in this node's position and then moves this node underneath it (as
+    /// its last child). Returns the new parent.
+    ///
+    /// Panics if the new parent is this node or one of its ancestors.
+    pub fn wrap_with(&self, new_parent: &Self) -> RcNode<Value> {
+        if new_parent.ptr_eq(self) || new_parent.is_ancestor_of(self) {
+            panic!("Unable to wrap a node with itself or one of its ancestors");
+        }
+        if self.parent().is_some() {
+            self.insert_prev_sibling(new_parent);
+        } else if new_parent.0.parent.is_some() {
+            new_parent.remove();
+        }
+        new_parent.append_child(self);
+        new_parent.debug_check("wrap_with");
+        new_parent.clone()
+    }
+    pub fn wrap_with_value(&self, value: Value) -> RcNode<Value> { self.wrap_with(&RcNode::new(value)) }
+
+    /// Moves the other node into this node's exact position, leaving this node (and its subtree)
+    /// detached. Returns the other node.
+    ///
+    /// Panics if the other node is an ancestor of this node.
+    pub fn replace_with(&self, other: &Self) -> RcNode<Value> {
+        if other.is_ancestor_of(self) {
+            panic!("Unable to replace a node with one of its ancestors");
+        }
+        if !other.ptr_eq(self) {
+            if self.parent().is_some() {
+                self.insert_prev_sibling(other);
+            } else if other.0.parent.is_some() {
+                other.remove();
+            }
+            self.remove();
+            other.debug_check("replace_with");
+        }
+        other.clone()
+    }
+
+    /// Exchanges the positions of two nodes (along with their subtrees). Panics if one of the
+    /// nodes is an ancestor of the other.
+    pub fn swap(a: &Self, b: &Self) {
+        if a.ptr_eq(b) {
+            return;
+        }
+        if a.is_ancestor_of(b) || b.is_ancestor_of(a) {
+            panic!("Unable to swap a node with its own ancestor");
+        }
+
+        let a_parent = a.parent();
+        let a_next = a.next_sibling();
+        if a_next.as_ref().is_some_and(|n| n.ptr_eq(b)) {
+            // Adjacent siblings, moving a after b is sufficient
+            b.insert_next_sibling(a);
+        } else {
+            // Move a into b's position
+            if b.parent().is_some() {
+                b.insert_prev_sibling(a);
+            } else {
+                a.remove();
+            }
+
+            // Move b into a's old position
+            if let Some(n) = a_next {
+                n.insert_prev_sibling(b);
+            } else if let Some(p) = a_parent {
+                p.append_child(b);
+            } else {
+                b.remove();
+            }
+        }
+        a.debug_check("swap");
+        b.debug_check("swap");
+    }
+
+    /// Moves the contiguous run of siblings from `first` through `last` (inclusive) to the given
+    /// position underneath the new parent. The sibling links are spliced as a unit, so only the
+    /// parent link of each moved node needs to be updated.
+    ///
+    /// Panics if `last` is not a following sibling of `first`, if the new parent is part of one of
+    /// the moved subtrees or if the position refers to a node that is not a child of the new parent.
+    pub fn move_sibling_range(first: &Self, last: &Self, new_parent: &Self, position: InsertPosition<&Self>) {
+        let old_parent = first.parent();
+        let same_parent = |a: Option<RcNode<Value>>, b: &Option<RcNode<Value>>| match (a, b) {
+            (Some(a), Some(b)) => a.ptr_eq(b),
+            (None, None) => true,
+            _ => false,
+        };
+
+        // Find the ancestor (or self) of the new parent that shares the range's sibling list
+        let mut anchor = Some(new_parent.clone());
+        while let Some(a) = anchor.clone() {
+            if same_parent(a.parent(), &old_parent) {
+                break;
+            }
+            anchor = a.parent();
+        }
+        let target = match position {
+            InsertPosition::Before(n) | InsertPosition::After(n) => {
+                if !n.parent().is_some_and(|p| p.ptr_eq(new_parent)) {
+                    panic!("The insert position must refer to a child of the new parent");
+                }
+                Some(n)
+            }
+            InsertPosition::First | InsertPosition::Last => None,
+        };
+
+        // Validate the range before any links are altered
+        let mut cur = first.clone();
+        loop {
+            if anchor.as_ref().is_some_and(|a| a.ptr_eq(&cur)) {
+                panic!("Unable to move a sibling range underneath itself");
+            }
+            if target.is_some_and(|n| n.ptr_eq(&cur)) {
+                panic!("The insert position must not be part of the moved range");
+            }
+            if cur.ptr_eq(last) {
+                break;
+            }
+            cur = cur.next_sibling().expect("The last node must be a following sibling of the first node");
+        }
+
+        // Detach the range from its current sibling list
+        let prev = first.prev_sibling();
+        let next = last.next_sibling();
+        if let Some(p) = old_parent {
+            if p.first_child().is_some_and(|c| c.ptr_eq(first)) {
+                p.0.first_child.set(next.as_ref());
+            }
+            if p.last_child().is_some_and(|c| c.ptr_eq(last)) {
+                p.0.last_child.set(prev.as_ref());
+            }
+        }
+        if let Some(s) = prev.as_ref() {
+            s.0.next_sibling.set(next.as_ref());
+        }
+        if let Some(s) = next.as_ref() {
+            s.0.prev_sibling.set(prev.as_ref());
+        }
+
+        // Splice the range into its new position
+        let (prev, next) = match position {
+            InsertPosition::First => (None, new_parent.first_child()),
+            InsertPosition::Last => (new_parent.last_child(), None),
+            InsertPosition::Before(n) => (n.prev_sibling(), Some(n.clone())),
+            InsertPosition::After(n) => (Some(n.clone()), n.next_sibling()),
+        };
+        first.0.prev_sibling.set(prev.as_ref());
+        last.0.next_sibling.set(next.as_ref());
+        if let Some(s) = prev {
+            s.0.next_sibling.set(Some(first));
+        } else {
+            new_parent.0.first_child.set(Some(first));
+        }
+        if let Some(s) = next {
+            s.0.prev_sibling.set(Some(last));
+        } else {
+            new_parent.0.last_child.set(Some(last));
+        }
+
+        let mut cur = first.clone();
+        loop {
+            cur.0.parent.set(Some(new_parent));
+            if cur.ptr_eq(last) {
+                break;
+            }
+            cur = cur.next_sibling().unwrap();
+        }
+        new_parent.debug_check("move_sibling_range");
+    }
+
+    /// Moves all siblings following this node to the end of the new parent's children. Returns
+    /// the new parent.
+    ///
+    /// The new parent is not moved, so it stays wherever the caller put it (for example, a detached
+    /// node that is inserted with `insert_next_sibling` afterwards). `split_children_after_value`
+    /// creates the new parent instead.
+    pub fn split_children_after(&self, new_parent: &Self) -> RcNode<Value> {
+        if let Some(first) = self.next_sibling() {
+            // Note: The siblings are only walked if the parent has been dropped
+            let last = self.parent().and_then(|p| p.last_child()).unwrap_or_else(|| {
+                let mut last = first.clone();
+                while let Some(n) = last.next_sibling() {
+                    last = n;
+                }
+                last
+            });
+            RcNode::move_sibling_range(&first, &last, new_parent, InsertPosition::Last);
+            self.debug_check("split_children_after");
+        }
+        new_parent.clone()
+    }
+    pub fn split_children_after_value(&self, value: Value) -> RcNode<Value> { self.split_children_after(&RcNode::new(value)) }
+
+    fn is_ancestor_of(&self, other: &Self) -> bool {
+        let mut cur = other.parent();
+        while let Some(p) = cur {
+            if p.ptr_eq(self) {
+                return true;
+            }
+            cur = p.parent();
+        }
+        false
     }
 
     /// Removes all child nodes from this node
@@ -84,6 +541,30 @@ impl<Value> RcNode<Value> {
         }
     }
 
+    /// Removes every descendant of this node for which the predicate returns false. Descendants
+    /// are visited in pre-order and this node itself is never tested. The mode determines whether
+    /// the children of a rejected node are removed with it or hoisted into its position.
+    pub fn retain_descendants<Predicate: FnMut(&RcNode<Value>) -> bool>(&self, mode: RetainMode, mut predicate: Predicate) {
+        let mut next = self.first_child();
+        while let Some(n) = next {
+            if predicate(&n) {
+                next = n.next_preorder(Some(self));
+            } else {
+                let first_child = n.first_child();
+                if mode == RetainMode::HoistChildren && first_child.is_some() {
+                    while let Some(c) = n.first_child() {
+                        n.insert_prev_sibling(&c);
+                    }
+                    next = first_child;
+                } else {
+                    next = n.next_in_subtree(Some(self));
+                }
+                n.remove();
+            }
+        }
+        self.debug_check("retain_descendants");
+    }
+
     /// Removes this node from its parent tree
     pub fn remove(&self) {
         let parent = self.parent();
@@ -92,7 +573,7 @@ impl<Value> RcNode<Value> {
         let prev = prev.as_ref();
         let next = next.as_ref();
 
-        if let Some(p) = parent {
+        if let Some(p) = &parent {
             // Note: self record is a child, so we are okay to call child().unwrap() here
             let is_first = p.first_child().unwrap().ptr_eq(self);
             let is_last = p.last_child().unwrap().ptr_eq(self);
@@ -115,25 +596,200 @@ impl<Value> RcNode<Value> {
         self.0.parent.set(None);
         self.0.prev_sibling.set(None);
         self.0.next_sibling.set(None);
+        if let Some(p) = parent {
+            p.debug_check("remove");
+        }
+    }
+
+    /// Verifies the links within the subtree of this node: parent and child links agree, the
+    /// first and last child links match the sibling list, sibling links are symmetric and no node
+    /// is listed twice (or underneath itself). The links of this node to its own parent and
+    /// siblings are not checked. Returns a report of every violation that was found.
+    pub fn check_subtree(&self) -> InvariantReport<RcNode<Value>> {
+        let mut report = InvariantReport::new();
+        // The parent that lists each node (as found by the walk)
+        let mut listed_by: HashMap<*const NodeData<Value>, Option<RcNode<Value>>> = HashMap::new();
+        listed_by.insert(Rc::as_ptr(&self.0), None);
+        let mut pending = vec![self.clone()];
+        while let Some(p) = pending.pop() {
+            report.checked_node();
+            let mut prev: Option<RcNode<Value>> = None;
+            let mut cur = p.first_child();
+            let mut complete = true;
+            while let Some(c) = cur {
+                if listed_by.contains_key(&Rc::as_ptr(&c.0)) {
+                    // Either the node is an ancestor of the list (a loop), or it was seen elsewhere
+                    let mut ancestor = Some(p.clone());
+                    let mut is_cycle = false;
+                    while let Some(a) = ancestor {
+                        if a.ptr_eq(&c) {
+                            is_cycle = true;
+                            break;
+                        }
+                        ancestor = listed_by[&Rc::as_ptr(&a.0)].clone();
+                    }
+                    report.push(c, if is_cycle { ViolationKind::Cycle } else { ViolationKind::ListedTwice });
+                    complete = false;
+                    break;
+                }
+                listed_by.insert(Rc::as_ptr(&c.0), Some(p.clone()));
+                if !c.parent().is_some_and(|cp| cp.ptr_eq(&p)) {
+                    report.push(c.clone(), ViolationKind::WrongParent);
+                }
+                if !same_node(c.prev_sibling().as_ref(), prev.as_ref()) {
+                    report.push(c.clone(), ViolationKind::WrongPrevSibling);
+                }
+                cur = c.next_sibling();
+                pending.push(c.clone());
+                prev = Some(c);
+            }
+            if complete && !same_node(p.last_child().as_ref(), prev.as_ref()) {
+                report.push(p.clone(), ViolationKind::WrongLastChild);
+            }
+        }
+        report
+    }
+
+    /// Checks the subtree of the root of this node after a structural change when the
+    /// `debug-checks` feature is enabled and panics if any invariant is violated.
+    fn debug_check(&self, operation: &str) {
+        if cfg!(feature = "debug-checks") {
+            // Note: The parent links are followed with a guard, since they may form a loop
+            let mut root = self.clone();
+            let mut seen = HashSet::new();
+            while let Some(p) = root.parent() {
+                if !seen.insert(Rc::as_ptr(&p.0)) {
+                    break;
+                }
+                root = p;
+            }
+            let report = root.check_subtree();
+            if !report.is_ok() {
+                let details: Vec<String> = report.violations().iter().map(|v| v.kind().to_string()).collect();
+                panic!("Tree invariants violated after {}: {}", operation, details.join(", "));
+            }
+        }
+    }
+}
+/// A node with a value that can be borrowed mutably (checked at runtime).
+///
+/// `RcNode` itself only hands out shared references to its value: any number of handles, as well as
+/// the links of the neighbouring nodes, refer to the same node, so a `value_mut` could only succeed
+/// while a handle is the sole owner, which is never the case for a linked node. Mutable values
+/// therefore use interior mutability chosen through the value type (`RefCell` here, `Cell` for
+/// `RcCellNode`), and these aliases add the borrowing helpers on top. Nodes with other value types
+/// are unaffected.
+pub type RcMutNode<Value> = RcNode<RefCell<Value>>;
+
+/// A node with a value that can be replaced without borrow tracking. Reading the value requires
+/// `Value: Copy`.
+pub type RcCellNode<Value> = RcNode<Cell<Value>>;
+
+impl<Value> RcNode<RefCell<Value>> {
+    pub fn new_mut(value: Value) -> Self { RcNode::new(RefCell::new(value)) }
+
+    /// Immutably borrows the value. Panics if the value is currently mutably borrowed.
+    pub fn borrow_value(&self) -> Ref<'_, Value> { self.value().borrow() }
+    /// Mutably borrows the value. Panics if the value is currently borrowed.
+    pub fn borrow_value_mut(&self) -> RefMut<'_, Value> { self.value().borrow_mut() }
+    pub fn try_borrow_value(&self) -> Result<Ref<'_, Value>, BorrowError> { self.value().try_borrow() }
+    pub fn try_borrow_value_mut(&self) -> Result<RefMut<'_, Value>, BorrowMutError> { self.value().try_borrow_mut() }
+
+    /// Replaces the value, returning the old one. Panics if the value is currently borrowed.
+    pub fn replace_value(&self, value: Value) -> Value { self.value().replace(value) }
+    /// Sets the value, dropping the old one. Panics if the value is currently borrowed.
+    pub fn set_value(&self, value: Value) { *self.borrow_value_mut() = value; }
+    /// Calls the function with a mutable reference to the value and returns its result. Panics if
+    /// the value is currently borrowed.
+    pub fn update_value<R, F: FnOnce(&mut Value) -> R>(&self, f: F) -> R { f(&mut *self.borrow_value_mut()) }
+}
+
+impl<Value> RcNode<Cell<Value>> {
+    pub fn new_cell(value: Value) -> Self { RcNode::new(Cell::new(value)) }
+
+    /// Replaces the value, returning the old one.
+    pub fn replace_value(&self, value: Value) -> Value { self.value().replace(value) }
+    /// Sets the value, dropping the old one.
+    pub fn set_value(&self, value: Value) { self.value().set(value) }
+}
+
+impl<Value: Copy> RcNode<Cell<Value>> {
+    /// Returns a copy of the value.
+    pub fn get_value(&self) -> Value { self.value().get() }
+    /// Replaces the value with the result of the function and returns the new value.
+    pub fn update_value<F: FnOnce(Value) -> Value>(&self, f: F) -> Value {
+        let v = f(self.get_value());
+        self.set_value(v);
+        v
     }
 }
+
 impl<Value> std::clone::Clone for RcNode<Value> {
     fn clone(&self) -> Self { RcNode(self.0.clone()) }
 }
 impl<Value: std::fmt::Debug> std::fmt::Debug for RcNode<Value> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        self.value().fmt(f)?;
-        if self.first_child().is_some() {
-            f.write_str(" [")?;
-            for c in self.children() {
-                c.fmt(f)?;
+        // Note: The subtree is walked using the links (instead of recursion) to support deep trees
+        let mut cur = self.clone();
+        loop {
+            cur.value().fmt(f)?;
+            if let Some(c) = cur.first_child() {
+                f.write_str(" [")?;
+                cur = c;
+                continue;
+            }
+            loop {
+                if cur.ptr_eq(self) {
+                    return Ok(());
+                }
+                if let Some(s) = cur.next_sibling() {
+                    cur = s;
+                    break;
+                }
+                cur = cur.parent().unwrap();
+                f.write_str("]")?;
             }
-            f.write_str("]")?;
         }
-        Ok(())
     }
 }
 
+/// A weak handle to an `RcNode`. Weak handles do not keep the node alive, which makes them
+/// suitable for caches and back-references.
+pub struct WeakRcNode<Value>(Weak<NodeData<Value>>);
+impl<Value> WeakRcNode<Value> {
+    /// Creates a weak handle that does not refer to any node. Calling `upgrade` on it always
+    /// returns `None`.
+    pub fn new() -> Self { WeakRcNode(Weak::new()) }
+
+    /// Returns the node if it is still alive.
+    pub fn upgrade(&self) -> Option<RcNode<Value>> { self.0.upgrade().map(RcNode) }
+
+    /// Returns true if both handles refer to the same node.
+    pub fn ptr_eq(&self, other: &Self) -> bool { self.0.ptr_eq(&other.0) }
+
+    /// Returns true if this handle refers to the specified node.
+    pub fn is_node(&self, node: &RcNode<Value>) -> bool { std::ptr::eq(self.0.as_ptr(), Rc::as_ptr(&node.0)) }
+
+    pub fn strong_count(&self) -> usize { self.0.strong_count() }
+    pub fn weak_count(&self) -> usize { self.0.weak_count() }
+}
+impl<Value> std::clone::Clone for WeakRcNode<Value> {
+    fn clone(&self) -> Self { WeakRcNode(self.0.clone()) }
+}
+impl<Value> std::default::Default for WeakRcNode<Value> {
+    fn default() -> Self { WeakRcNode::new() }
+}
+impl<Value> std::cmp::PartialEq for WeakRcNode<Value> {
+    fn eq(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+impl<Value> std::cmp::Eq for WeakRcNode<Value> {}
+impl<Value> std::hash::Hash for WeakRcNode<Value> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) { self.0.as_ptr().hash(state) }
+}
+impl<Value> std::fmt::Debug for WeakRcNode<Value> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> { f.write_str("(WeakRcNode)") }
+}
+
 pub struct RcNodeSiblingIter<Value> {
     next: Option<RcNode<Value>>,
 }
@@ -149,3 +805,11 @@ impl<Value> std::iter::Iterator for RcNodeSiblingIter<Value> {
         }
     }
 }
+
+fn same_node<Value>(a: Option<&RcNode<Value>>, b: Option<&RcNode<Value>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.ptr_eq(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,556 @@
+//! A small CSS-like selector language for querying trees.
+//!
+//! Supported syntax:
+//!
+//! - `*` and type names (`div`), matched with `Matcher::matches_name`
+//! - attributes: `[name]` and `[name=value]` (the value may be quoted), matched with
+//!   `Matcher::matches_attribute`
+//! - `:first-child`, `:last-child`, `:only-child`, `:nth-child(an+b)` and `:nth-last-child(an+b)`
+//!   (including `odd` and `even`)
+//! - `:not(selectors)` and `:has(relative selectors)`, for example `:has(> img)`
+//! - the combinators `a b` (descendant), `a > b` (child), `a + b` (next sibling) and `a ~ b`
+//!   (subsequent sibling), and selector lists separated by commas
+//!
+//! Positions such as `:first-child` are counted among siblings, so a root node without siblings is
+//! its own first and last child.
+
+use arena::{IndexType, NodeRef, Storage};
+use rctree::RcNode;
+use std;
+use sync::ArcNode;
+use walk::Links;
+
+/// Implemented by node values so they can be matched by type names and attributes.
+pub trait Matcher {
+    /// Returns true if the value matches the type selector `name` (as in `div`).
+    fn matches_name(&self, name: &str) -> bool;
+    /// Returns true if the value has the attribute `name`. If `value` is given, the attribute must
+    /// also be equal to it (as in `[type=text]`).
+    fn matches_attribute(&self, name: &str, value: Option<&str>) -> bool;
+}
+
+/// The navigation a selector needs in order to be evaluated. Implemented for `arena::NodeRef`,
+/// `rctree::RcNode` and `sync::ArcNode`.
+pub trait SelectNode: Clone {
+    type Value: Matcher + ?Sized;
+
+    fn value(&self) -> &Self::Value;
+    fn parent(&self) -> Option<Self>;
+    fn first_child(&self) -> Option<Self>;
+    fn prev_sibling(&self) -> Option<Self>;
+    fn next_sibling(&self) -> Option<Self>;
+    /// Returns true if both handles refer to the same node.
+    fn is_same(&self, other: &Self) -> bool;
+}
+
+impl<'a, IdType: Copy + Eq + 'a, T: Matcher + 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> SelectNode for NodeRef<'a, IdType, T, Ix, S> {
+    type Value = T;
+
+    fn value(&self) -> &T { NodeRef::value(self) }
+    fn parent(&self) -> Option<Self> { NodeRef::parent(self) }
+    fn first_child(&self) -> Option<Self> { NodeRef::first_child(self) }
+    fn prev_sibling(&self) -> Option<Self> { NodeRef::prev_sibling(self) }
+    fn next_sibling(&self) -> Option<Self> { NodeRef::next_sibling(self) }
+    fn is_same(&self, other: &Self) -> bool { self == other }
+}
+impl<Value: Matcher> SelectNode for RcNode<Value> {
+    type Value = Value;
+
+    fn value(&self) -> &Value { RcNode::value(self) }
+    fn parent(&self) -> Option<Self> { RcNode::parent(self) }
+    fn first_child(&self) -> Option<Self> { RcNode::first_child(self) }
+    fn prev_sibling(&self) -> Option<Self> { RcNode::prev_sibling(self) }
+    fn next_sibling(&self) -> Option<Self> { RcNode::next_sibling(self) }
+    fn is_same(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+impl<Value: Matcher> SelectNode for ArcNode<Value> {
+    type Value = Value;
+
+    fn value(&self) -> &Value { ArcNode::value(self) }
+    fn parent(&self) -> Option<Self> { ArcNode::parent(self) }
+    fn first_child(&self) -> Option<Self> { ArcNode::first_child(self) }
+    fn prev_sibling(&self) -> Option<Self> { ArcNode::prev_sibling(self) }
+    fn next_sibling(&self) -> Option<Self> { ArcNode::next_sibling(self) }
+    fn is_same(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Clone, Debug)]
+enum Simple {
+    Name(String),
+    Attribute(String, Option<String>),
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    NthChild(i64, i64),
+    NthLastChild(i64, i64),
+    Not(Vec<Complex>),
+    Has(Vec<Complex>),
+}
+
+/// A sequence of compound selectors (each a list of simple selectors, empty for `*`) along with
+/// the combinator that precedes it. The first combinator is only used by relative selectors (in
+/// `:has`), where it relates the first compound to the anchor node.
+#[derive(Clone, Debug)]
+struct Complex {
+    parts: Vec<(Combinator, Vec<Simple>)>,
+}
+
+/// A compiled selector (list). Parse once with `Selector::parse` and evaluate it with `select`
+/// or `matches`.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    alternatives: Vec<Complex>,
+}
+impl Selector {
+    pub fn parse(s: &str) -> Result<Selector, SelectorError> {
+        let mut p = Parser { s, pos: 0 };
+        p.skip_ws();
+        let alternatives = p.parse_list(false)?;
+        if p.pos < s.len() {
+            return Err(p.error("unexpected character"));
+        }
+        Ok(Selector { alternatives })
+    }
+
+    /// Returns true if the node matches the selector. Combinators may match ancestors and
+    /// siblings anywhere in the tree.
+    pub fn matches<N: SelectNode>(&self, node: &N) -> bool { self.matches_with(node, &mut Positions::new()) }
+
+    fn matches_with<N: SelectNode>(&self, node: &N, positions: &mut Positions<N>) -> bool {
+        self.alternatives.iter().any(|c| matches_complex(c, c.parts.len() - 1, node, None, positions))
+    }
+
+    /// Returns the matching nodes of the subtree starting at `root` (including `root` itself) in
+    /// document order.
+    pub fn select<N: SelectNode>(&self, root: &N) -> Vec<N> {
+        let mut result = Vec::new();
+        let mut positions = Positions::new();
+        let mut cur = Some(root.clone());
+        while let Some(n) = cur {
+            if self.matches_with(&n, &mut positions) {
+                result.push(n.clone());
+            }
+            cur = next_preorder(&n, Some(root));
+        }
+        result
+    }
+
+    /// Returns the first matching node of the subtree starting at `root` in document order.
+    pub fn select_first<N: SelectNode>(&self, root: &N) -> Option<N> {
+        let mut positions = Positions::new();
+        let mut cur = Some(root.clone());
+        while let Some(n) = cur {
+            if self.matches_with(&n, &mut positions) {
+                return Some(n);
+            }
+            cur = next_preorder(&n, Some(root));
+        }
+        None
+    }
+}
+impl std::str::FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Selector::parse(s) }
+}
+
+/// Returned when a string cannot be parsed as a `Selector`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SelectorError {
+    position: usize,
+    message: &'static str,
+}
+impl SelectorError {
+    /// Returns the byte offset of the problem within the parsed string.
+    pub fn position(&self) -> usize { self.position }
+    pub fn message(&self) -> &str { self.message }
+}
+impl std::fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "Invalid selector at position {}: {}", self.position, self.message) }
+}
+impl std::error::Error for SelectorError {}
+
+/// Adapts a `SelectNode` to the shared pre-order walk.
+#[derive(Clone)]
+struct Walk<N>(N);
+impl<N: SelectNode> Links for Walk<N> {
+    fn parent(&self) -> Option<Self> { self.0.parent().map(Walk) }
+    fn first_child(&self) -> Option<Self> { self.0.first_child().map(Walk) }
+    fn next_sibling(&self) -> Option<Self> { self.0.next_sibling().map(Walk) }
+    fn is_same(&self, other: &Self) -> bool { self.0.is_same(&other.0) }
+}
+
+/// Returns the node following `n` in pre-order, without leaving the subtree of `root` (if given).
+fn next_preorder<N: SelectNode>(n: &N, root: Option<&N>) -> Option<N> {
+    let root = root.map(|r| Walk(r.clone()));
+    Walk(n.clone()).next_preorder(root.as_ref()).map(|w| w.0)
+}
+
+fn matches_complex<N: SelectNode>(c: &Complex, idx: usize, n: &N, anchor: Option<&N>, positions: &mut Positions<N>) -> bool {
+    let (combinator, ref compound) = c.parts[idx];
+    if !compound.iter().all(|s| matches_simple(s, n, positions)) {
+        return false;
+    }
+    if idx == 0 {
+        return match anchor {
+            Some(a) => is_related(combinator, a, n),
+            None => true,
+        };
+    }
+    match combinator {
+        Combinator::Child => n.parent().is_some_and(|p| matches_complex(c, idx - 1, &p, anchor, positions)),
+        Combinator::NextSibling => n.prev_sibling().is_some_and(|s| matches_complex(c, idx - 1, &s, anchor, positions)),
+        Combinator::Descendant => {
+            let mut cur = n.parent();
+            while let Some(p) = cur {
+                if matches_complex(c, idx - 1, &p, anchor, positions) {
+                    return true;
+                }
+                cur = p.parent();
+            }
+            false
+        }
+        Combinator::SubsequentSibling => {
+            let mut cur = n.prev_sibling();
+            while let Some(s) = cur {
+                if matches_complex(c, idx - 1, &s, anchor, positions) {
+                    return true;
+                }
+                cur = s.prev_sibling();
+            }
+            false
+        }
+    }
+}
+
+/// Returns true if `n` is related to `anchor` through the combinator.
+fn is_related<N: SelectNode>(combinator: Combinator, anchor: &N, n: &N) -> bool {
+    match combinator {
+        Combinator::Child => n.parent().is_some_and(|p| p.is_same(anchor)),
+        Combinator::NextSibling => n.prev_sibling().is_some_and(|s| s.is_same(anchor)),
+        Combinator::Descendant => {
+            let mut cur = n.parent();
+            while let Some(p) = cur {
+                if p.is_same(anchor) {
+                    return true;
+                }
+                cur = p.parent();
+            }
+            false
+        }
+        Combinator::SubsequentSibling => {
+            let mut cur = n.prev_sibling();
+            while let Some(s) = cur {
+                if s.is_same(anchor) {
+                    return true;
+                }
+                cur = s.prev_sibling();
+            }
+            false
+        }
+    }
+}
+
+/// Returns true if a node related to `anchor` (as required by the leading combinator) matches the
+/// relative selector.
+fn has_match<N: SelectNode>(c: &Complex, anchor: &N, positions: &mut Positions<N>) -> bool {
+    let last = c.parts.len() - 1;
+    // Matches can only be found among the descendants of the anchor, or (for sibling combinators)
+    // the following siblings of the anchor and their descendants.
+    let (mut cur, root) = match c.parts[0].0 {
+        Combinator::Descendant | Combinator::Child => (anchor.first_child(), Some(anchor.clone())),
+        Combinator::NextSibling | Combinator::SubsequentSibling => (anchor.next_sibling(), anchor.parent()),
+    };
+    while let Some(n) = cur {
+        if matches_complex(c, last, &n, Some(anchor), positions) {
+            return true;
+        }
+        cur = next_preorder(&n, root.as_ref());
+    }
+    false
+}
+
+fn matches_simple<N: SelectNode>(s: &Simple, n: &N, positions: &mut Positions<N>) -> bool {
+    match *s {
+        Simple::Name(ref name) => n.value().matches_name(name),
+        Simple::Attribute(ref name, ref value) => n.value().matches_attribute(name, value.as_ref().map(|v| v.as_str())),
+        Simple::FirstChild => n.prev_sibling().is_none(),
+        Simple::LastChild => n.next_sibling().is_none(),
+        Simple::OnlyChild => n.prev_sibling().is_none() && n.next_sibling().is_none(),
+        Simple::NthChild(a, b) => nth_matches(a, b, positions.get(n).0),
+        Simple::NthLastChild(a, b) => nth_matches(a, b, positions.get(n).1),
+        Simple::Not(ref list) => !list.iter().any(|c| matches_complex(c, c.parts.len() - 1, n, None, positions)),
+        Simple::Has(ref list) => list.iter().any(|c| has_match(c, n, positions)),
+    }
+}
+
+/// Remembers the sibling positions computed during a walk, so the siblings of a node are only
+/// counted once (instead of once per sibling) when the nodes are visited in document order.
+struct Positions<N> {
+    /// The nodes along with their 1-based positions from the start and from the end. There is
+    /// roughly one entry per level of the walk, a node replaces the entry of its previous sibling.
+    entries: Vec<(N, i64, i64)>,
+}
+impl<N: SelectNode> Positions<N> {
+    fn new() -> Self { Positions { entries: Vec::new() } }
+
+    /// Returns the position of the node among its siblings, counted from the start and from the end.
+    fn get(&mut self, n: &N) -> (i64, i64) {
+        if let Some(&(_, pos, pos_from_end)) = self.entries.iter().rev().find(|e| e.0.is_same(n)) {
+            return (pos, pos_from_end);
+        }
+        if let Some(prev) = n.prev_sibling() {
+            if let Some(i) = self.entries.iter().rposition(|e| e.0.is_same(&prev)) {
+                // Entries added after the previous sibling belong to its subtree (or to unrelated
+                // lookups), so they are unlikely to be needed again
+                let (pos, pos_from_end) = (self.entries[i].1 + 1, self.entries[i].2 - 1);
+                self.entries.truncate(i);
+                self.entries.push((n.clone(), pos, pos_from_end));
+                return (pos, pos_from_end);
+            }
+        }
+        let pos = sibling_count(n, |n| n.prev_sibling()) + 1;
+        let pos_from_end = sibling_count(n, |n| n.next_sibling()) + 1;
+        self.entries.push((n.clone(), pos, pos_from_end));
+        (pos, pos_from_end)
+    }
+}
+
+/// Returns the number of siblings reached through `step`.
+fn sibling_count<N: SelectNode, F: Fn(&N) -> Option<N>>(n: &N, step: F) -> i64 {
+    let mut count = 0;
+    let mut cur = step(n);
+    while let Some(s) = cur {
+        count += 1;
+        cur = step(&s);
+    }
+    count
+}
+
+/// Returns true if `pos = a*k + b` for some k >= 0. A calculation that overflows (only possible
+/// with values of `b` near the limits of `i64`) counts as no match.
+fn nth_matches(a: i64, b: i64, pos: i64) -> bool {
+    if a == 0 {
+        return pos == b;
+    }
+    match pos.checked_sub(b) {
+        Some(diff) => diff.checked_rem(a) == Some(0) && diff.checked_div(a).is_some_and(|k| k >= 0),
+        None => false,
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> SelectorError { SelectorError { position: self.pos, message } }
+    fn peek(&self) -> Option<char> { self.s[self.pos..].chars().next() }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, c: char, message: &'static str) -> Result<(), SelectorError> { if self.eat(c) { Ok(()) } else { Err(self.error(message)) } }
+    fn skip_ws(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+        self.pos > start
+    }
+
+    /// Parses a comma-separated list, stopping at the end of the input or at a `)`.
+    fn parse_list(&mut self, relative: bool) -> Result<Vec<Complex>, SelectorError> {
+        let mut list = vec![self.parse_complex(relative)?];
+        while self.eat(',') {
+            self.skip_ws();
+            list.push(self.parse_complex(relative)?);
+        }
+        Ok(list)
+    }
+
+    fn parse_combinator(&mut self) -> Option<Combinator> {
+        let result = match self.peek() {
+            Some('>') => Combinator::Child,
+            Some('+') => Combinator::NextSibling,
+            Some('~') => Combinator::SubsequentSibling,
+            _ => return None,
+        };
+        self.pos += 1;
+        self.skip_ws();
+        Some(result)
+    }
+
+    fn parse_complex(&mut self, relative: bool) -> Result<Complex, SelectorError> {
+        let leading = if relative { self.parse_combinator().unwrap_or(Combinator::Descendant) } else { Combinator::Descendant };
+        let mut parts = vec![(leading, self.parse_compound()?)];
+        loop {
+            let had_ws = self.skip_ws();
+            let combinator = match self.parse_combinator() {
+                Some(c) => c,
+                None if had_ws && !matches!(self.peek(), None | Some(',') | Some(')')) => Combinator::Descendant,
+                None => break,
+            };
+            parts.push((combinator, self.parse_compound()?));
+        }
+        Ok(Complex { parts })
+    }
+
+    fn parse_compound(&mut self) -> Result<Vec<Simple>, SelectorError> {
+        let mut result = Vec::new();
+        let mut any = false;
+        if self.eat('*') {
+            any = true;
+        } else if self.peek().is_some_and(is_ident_char) {
+            result.push(Simple::Name(self.parse_ident()?));
+        }
+        loop {
+            if self.eat('[') {
+                result.push(self.parse_attribute()?);
+            } else if self.eat(':') {
+                result.push(self.parse_pseudo()?);
+            } else {
+                break;
+            }
+        }
+        if result.is_empty() && !any {
+            return Err(self.error("expected a selector"));
+        }
+        Ok(result)
+    }
+
+    fn parse_ident(&mut self) -> Result<String, SelectorError> {
+        let start = self.pos;
+        while let Some(c) = self.peek().filter(|&c| is_ident_char(c)) {
+            self.pos += c.len_utf8();
+        }
+        if self.pos == start {
+            Err(self.error("expected a name"))
+        } else {
+            Ok(self.s[start..self.pos].to_string())
+        }
+    }
+
+    fn parse_attribute(&mut self) -> Result<Simple, SelectorError> {
+        self.skip_ws();
+        let name = self.parse_ident()?;
+        self.skip_ws();
+        let value = if self.eat('=') {
+            self.skip_ws();
+            let v = match self.peek() {
+                Some(q) if q == '"' || q == '\'' => self.parse_quoted(q)?,
+                _ => self.parse_ident()?,
+            };
+            self.skip_ws();
+            Some(v)
+        } else {
+            None
+        };
+        self.expect(']', "expected ']'")?;
+        Ok(Simple::Attribute(name, value))
+    }
+
+    fn parse_quoted(&mut self, quote: char) -> Result<String, SelectorError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut result = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            if c == quote {
+                return Ok(result);
+            } else if c == '\\' {
+                if let Some(escaped) = self.peek() {
+                    self.pos += escaped.len_utf8();
+                    result.push(escaped);
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        self.pos = start;
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_pseudo(&mut self) -> Result<Simple, SelectorError> {
+        let start = self.pos;
+        let name = self.parse_ident()?;
+        let result = match name.as_str() {
+            "first-child" => Simple::FirstChild,
+            "last-child" => Simple::LastChild,
+            "only-child" => Simple::OnlyChild,
+            "nth-child" | "nth-last-child" => {
+                self.expect('(', "expected '('")?;
+                let arg_start = self.pos;
+                let arg_end = self.s[arg_start..].find(')').map(|i| arg_start + i).ok_or_else(|| self.error("expected ')'"))?;
+                let (a, b) = parse_nth(&self.s[arg_start..arg_end]).ok_or(SelectorError {
+                    position: arg_start,
+                    message: "invalid an+b expression",
+                })?;
+                self.pos = arg_end + 1;
+                if name == "nth-child" {
+                    Simple::NthChild(a, b)
+                } else {
+                    Simple::NthLastChild(a, b)
+                }
+            }
+            "not" | "has" => {
+                self.expect('(', "expected '('")?;
+                self.skip_ws();
+                let list = self.parse_list(name == "has")?;
+                self.skip_ws();
+                self.expect(')', "expected ')'")?;
+                if name == "not" {
+                    Simple::Not(list)
+                } else {
+                    Simple::Has(list)
+                }
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error("unknown pseudo-class"));
+            }
+        };
+        Ok(result)
+    }
+}
+
+fn is_ident_char(c: char) -> bool { c.is_alphanumeric() || c == '-' || c == '_' }
+
+/// Parses `odd`, `even`, `b`, `an`, `an+b` and similar forms, returning `(a, b)`.
+fn parse_nth(s: &str) -> Option<(i64, i64)> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_lowercase();
+    match s.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let parse_int = |v: &str| if v.starts_with('+') && v[1..].starts_with(['+', '-']) { None } else { v.parse::<i64>().ok() };
+    match s.find('n') {
+        None => parse_int(&s).map(|b| (0, b)),
+        Some(i) => {
+            let a = match &s[..i] {
+                "" | "+" => 1,
+                "-" => -1,
+                v => parse_int(v)?,
+            };
+            let b = match &s[i + 1..] {
+                "" => 0,
+                v if v.starts_with(['+', '-']) => parse_int(v)?,
+                _ => return None,
+            };
+            Some((a, b))
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use std;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+
+// Every node refers to the lock of the tree it belongs to. Structural changes hold the lock for
+// writing for their entire duration, while reading a link (or a list of children) holds it for
+// reading, so readers never observe an edit that is still in progress. Moving a subtree into
+// another tree switches the nodes of the subtree over to the lock of that tree. Removing a subtree
+// keeps its lock, so a detached subtree may still share the lock of its former tree.
+type TreeLock = Arc<RwLock<()>>;
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> { m.lock().unwrap_or_else(PoisonError::into_inner) }
+fn read_lock(l: &RwLock<()>) -> RwLockReadGuard<'_, ()> { l.read().unwrap_or_else(PoisonError::into_inner) }
+fn write_lock(l: &RwLock<()>) -> RwLockWriteGuard<'_, ()> { l.write().unwrap_or_else(PoisonError::into_inner) }
+
+struct Links<Value> {
+    parent: Option<Weak<NodeData<Value>>>,
+    next_sibling: Option<Arc<NodeData<Value>>>,
+    prev_sibling: Option<Weak<NodeData<Value>>>,
+    first_child: Option<Arc<NodeData<Value>>>,
+    last_child: Option<Arc<NodeData<Value>>>,
+}
+
+struct NodeData<Value> {
+    tree: Mutex<TreeLock>,
+    links: Mutex<Links<Value>>,
+    value: Value,
+}
+impl<Value> std::ops::Drop for NodeData<Value> {
+    fn drop(&mut self) {
+        // Letting the strong links drop on their own would recurse once per child and sibling,
+        // so nodes that are only owned by these links are unlinked and dropped one at a time.
+        let mut pending = Vec::new();
+        take_strong_links(&mut self.links, &mut pending);
+        while let Some(d) = pending.pop() {
+            if let Ok(mut d) = Arc::try_unwrap(d) {
+                take_strong_links(&mut d.links, &mut pending);
+            }
+        }
+    }
+}
+fn take_strong_links<Value>(links: &mut Mutex<Links<Value>>, pending: &mut Vec<Arc<NodeData<Value>>>) {
+    let links = links.get_mut().unwrap_or_else(PoisonError::into_inner);
+    pending.extend(links.last_child.take());
+    pending.extend(links.first_child.take());
+    pending.extend(links.next_sibling.take());
+}
+
+/// A thread-safe counterpart to `rctree::RcNode`. Structural changes are visible to every holder
+/// of the tree, on any thread.
+pub struct ArcNode<Value>(Arc<NodeData<Value>>);
+impl<Value> ArcNode<Value> {
+    pub fn new(value: Value) -> Self {
+        let links = Links {
+            parent: None,
+            next_sibling: None,
+            prev_sibling: None,
+            first_child: None,
+            last_child: None,
+        };
+        ArcNode(Arc::new(NodeData {
+            tree: Mutex::new(Arc::new(RwLock::new(()))),
+            links: Mutex::new(links),
+            value,
+        }))
+    }
+
+    fn links(&self) -> MutexGuard<'_, Links<Value>> { lock(&self.0.links) }
+    fn tree(&self) -> TreeLock { lock(&self.0.tree).clone() }
+    fn in_tree(&self, tree: &TreeLock) -> bool { Arc::ptr_eq(&lock(&self.0.tree), tree) }
+
+    /// Calls the function while holding the lock of this node's tree for reading.
+    fn read<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        loop {
+            let tree = self.tree();
+            let _guard = read_lock(&tree);
+            // The node may have been moved to another tree while waiting for the lock
+            if self.in_tree(&tree) {
+                return f();
+            }
+        }
+    }
+
+    /// Calls the function while holding the lock of this node's tree for writing, along with the
+    /// lock of the tree of the node that is moved into it (if any). Afterwards, the subtree of the
+    /// moved node is switched over to the lock of this node's tree.
+    fn edit<R, F: FnOnce() -> R>(&self, moved: Option<&Self>, f: F) -> R {
+        loop {
+            let tree = self.tree();
+            let other = moved.map(|m| m.tree()).filter(|t| !Arc::ptr_eq(t, &tree));
+            // Note: Two locks are always taken in the same (address) order to avoid deadlocks
+            let guards = match other {
+                Some(ref o) if Arc::as_ptr(o) < Arc::as_ptr(&tree) => (write_lock(o), Some(write_lock(&tree))),
+                Some(ref o) => (write_lock(&tree), Some(write_lock(o))),
+                None => (write_lock(&tree), None),
+            };
+            // Either node may have been moved to another tree while waiting for the locks
+            if !self.in_tree(&tree) || !moved.is_none_or(|m| m.in_tree(other.as_ref().unwrap_or(&tree))) {
+                continue;
+            }
+            let result = f();
+            if let (Some(m), Some(_)) = (moved, &other) {
+                m.set_tree(&tree);
+            }
+            drop(guards);
+            return result;
+        }
+    }
+
+    /// Switches this node and its descendants over to the lock of another tree.
+    fn set_tree(&self, tree: &TreeLock) {
+        let mut cur = self.clone();
+        loop {
+            *lock(&cur.0.tree) = tree.clone();
+            if let Some(c) = cur.first_child_link() {
+                cur = c;
+                continue;
+            }
+            loop {
+                if cur.ptr_eq(self) {
+                    return;
+                }
+                if let Some(s) = cur.next_sibling_link() {
+                    cur = s;
+                    break;
+                }
+                cur = cur.parent_link().unwrap();
+            }
+        }
+    }
+
+    /// Returns true if both handles refer to the same node.
+    pub fn ptr_eq(&self, other: &Self) -> bool { Arc::ptr_eq(&self.0, &other.0) }
+
+    /// Creates a weak handle to this node. The weak handle does not keep the node (or its
+    /// subtree) alive.
+    pub fn downgrade(&self) -> WeakArcNode<Value> { WeakArcNode(Arc::downgrade(&self.0)) }
+
+    /// Returns the number of strong references to this node. Note that this includes the internal
+    /// references held by the parent (first/last child) and previous sibling (next sibling).
+    pub fn strong_count(&self) -> usize { Arc::strong_count(&self.0) }
+
+    /// Returns the number of weak references to this node. Note that this includes the internal
+    /// references held by the children (parent) and next sibling (previous sibling).
+    pub fn weak_count(&self) -> usize { Arc::weak_count(&self.0) }
+
+    pub fn parent(&self) -> Option<ArcNode<Value>> { self.read(|| self.parent_link()) }
+    pub fn next_sibling(&self) -> Option<ArcNode<Value>> { self.read(|| self.next_sibling_link()) }
+    pub fn prev_sibling(&self) -> Option<ArcNode<Value>> { self.read(|| self.prev_sibling_link()) }
+    pub fn first_child(&self) -> Option<ArcNode<Value>> { self.read(|| self.first_child_link()) }
+    pub fn last_child(&self) -> Option<ArcNode<Value>> { self.read(|| self.last_child_link()) }
+    pub fn value(&self) -> &Value { &self.0.value }
+
+    /// Returns the children of this node. The list is read at once (while holding the lock of the
+    /// tree), so it reflects either all or none of the changes made by a concurrent edit.
+    pub fn children(&self) -> ArcNodeSiblingIter<Value> {
+        let children = self.read(|| {
+            let mut result = Vec::new();
+            let mut next = self.first_child_link();
+            while let Some(n) = next {
+                next = n.next_sibling_link();
+                result.push(n);
+            }
+            result
+        });
+        ArcNodeSiblingIter { iter: children.into_iter() }
+    }
+
+    // Note: The following functions read the links without taking the lock of the tree, they are
+    // used by the edits (which already hold it)
+    fn parent_link(&self) -> Option<ArcNode<Value>> { self.links().parent.as_ref().and_then(|d| d.upgrade()).map(ArcNode) }
+    fn next_sibling_link(&self) -> Option<ArcNode<Value>> { self.links().next_sibling.clone().map(ArcNode) }
+    fn prev_sibling_link(&self) -> Option<ArcNode<Value>> { self.links().prev_sibling.as_ref().and_then(|d| d.upgrade()).map(ArcNode) }
+    fn first_child_link(&self) -> Option<ArcNode<Value>> { self.links().first_child.clone().map(ArcNode) }
+    fn last_child_link(&self) -> Option<ArcNode<Value>> { self.links().last_child.clone().map(ArcNode) }
+
+    fn set_parent(&self, n: Option<&Self>) { self.links().parent = n.map(|n| Arc::downgrade(&n.0)) }
+    fn set_next_sibling(&self, n: Option<&Self>) { self.links().next_sibling = n.map(|n| n.0.clone()) }
+    fn set_prev_sibling(&self, n: Option<&Self>) { self.links().prev_sibling = n.map(|n| Arc::downgrade(&n.0)) }
+    fn set_first_child(&self, n: Option<&Self>) { self.links().first_child = n.map(|n| n.0.clone()) }
+    fn set_last_child(&self, n: Option<&Self>) { self.links().last_child = n.map(|n| n.0.clone()) }
+    fn has_parent_link(&self) -> bool { self.links().parent.is_some() }
+
+    /// Add the specified child to this node after the last existing child (if any).
+    /// If the node already exists in a differnt tree/location, then it is removed from the old location and added to this one.
+    pub fn append_child(&self, child: &Self) { self.edit(Some(child), || self.locked_append_child(child)) }
+    pub fn append_child_value(&self, value: Value) -> ArcNode<Value> {
+        let child = ArcNode::new(value);
+        self.append_child(&child);
+        child
+    }
+    fn locked_append_child(&self, child: &Self) {
+        if child.has_parent_link() {
+            child.locked_remove();
+        }
+        child.set_parent(Some(self));
+        if let Some(lc) = self.last_child_link() {
+            self.set_last_child(Some(child));
+            child.set_prev_sibling(Some(&lc));
+            lc.set_next_sibling(Some(child));
+        } else {
+            self.set_first_child(Some(child));
+            self.set_last_child(Some(child));
+        }
+    }
+
+    /// Add the specified child to this node before the first existing child (if any).
+    /// If the node already exists in a differnt tree/location, then it is removed from the old location and added to this one.
+    pub fn prepend_child(&self, child: &Self) { self.edit(Some(child), || self.locked_prepend_child(child)) }
+    pub fn prepend_child_value(&self, value: Value) -> ArcNode<Value> {
+        let child = ArcNode::new(value);
+        self.prepend_child(&child);
+        child
+    }
+    fn locked_prepend_child(&self, child: &Self) {
+        if child.has_parent_link() {
+            child.locked_remove();
+        }
+        child.set_parent(Some(self));
+        if let Some(fc) = self.first_child_link() {
+            self.set_first_child(Some(child));
+            child.set_next_sibling(Some(&fc));
+            fc.set_prev_sibling(Some(child));
+        } else {
+            self.set_first_child(Some(child));
+            self.set_last_child(Some(child));
+        }
+    }
+
+    /// Inserts the specified node immediately after this node. If this node does not have a
+    /// parent, then this method will panic.
+    pub fn insert_next_sibling(&self, sibling: &Self) { self.edit(Some(sibling), || self.locked_insert_next_sibling(sibling)) }
+    fn locked_insert_next_sibling(&self, sibling: &Self) {
+        let parent = self.parent_link().expect("This node must have a parent node for insert_next_sibling to work.");
+        if sibling.ptr_eq(self) {
+            return;
+        }
+        if sibling.has_parent_link() {
+            sibling.locked_remove();
+        }
+        if let Some(next) = self.next_sibling_link() {
+            sibling.set_parent(Some(&parent));
+            sibling.set_prev_sibling(Some(self));
+            sibling.set_next_sibling(Some(&next));
+            self.set_next_sibling(Some(sibling));
+            next.set_prev_sibling(Some(sibling));
+        } else {
+            // Current node is the last one in the list, this is the same as calling append_child on the parent
+            parent.locked_append_child(sibling);
+        }
+    }
+    pub fn insert_next_sibling_value(&self, value: Value) -> ArcNode<Value> {
+        let sibling = ArcNode::new(value);
+        self.insert_next_sibling(&sibling);
+        sibling
+    }
+
+    /// Inserts the specified node immediately before this node. If this node does not have a
+    /// parent, then this method will panic.
+    pub fn insert_prev_sibling(&self, sibling: &Self) { self.edit(Some(sibling), || self.locked_insert_prev_sibling(sibling)) }
+    fn locked_insert_prev_sibling(&self, sibling: &Self) {
+        let parent = self.parent_link().expect("This node must have a parent node for insert_prev_sibling to work.");
+        if sibling.ptr_eq(self) {
+            return;
+        }
+        if sibling.has_parent_link() {
+            sibling.locked_remove();
+        }
+        if let Some(prev) = self.prev_sibling_link() {
+            sibling.set_parent(Some(&parent));
+            sibling.set_prev_sibling(Some(&prev));
+            sibling.set_next_sibling(Some(self));
+            prev.set_next_sibling(Some(sibling));
+            self.set_prev_sibling(Some(sibling));
+        } else {
+            // Current node is the first one in the list, this is the same as calling prepend_child on the parent
+            parent.locked_prepend_child(sibling);
+        }
+    }
+    pub fn insert_prev_sibling_value(&self, value: Value) -> ArcNode<Value> {
+        let sibling = ArcNode::new(value);
+        self.insert_prev_sibling(&sibling);
+        sibling
+    }
+
+    /// Removes all child nodes from this node
+    pub fn remove_children(&self) {
+        self.edit(None, || {
+            while let Some(c) = self.first_child_link() {
+                c.locked_remove();
+            }
+        })
+    }
+
+    /// Removes this node from its parent tree
+    pub fn remove(&self) { self.edit(None, || self.locked_remove()) }
+    fn locked_remove(&self) {
+        let parent = self.parent_link();
+        let prev = self.prev_sibling_link();
+        let next = self.next_sibling_link();
+        let prev = prev.as_ref();
+        let next = next.as_ref();
+
+        if let Some(p) = parent {
+            // Note: self record is a child, so we are okay to call child().unwrap() here
+            let is_first = p.first_child_link().unwrap().ptr_eq(self);
+            let is_last = p.last_child_link().unwrap().ptr_eq(self);
+            if is_first {
+                p.set_first_child(next);
+            }
+            if is_last {
+                p.set_last_child(prev);
+            }
+        }
+
+        if let Some(s) = prev {
+            s.set_next_sibling(next);
+        }
+
+        if let Some(s) = next {
+            s.set_prev_sibling(prev);
+        }
+
+        self.set_parent(None);
+        self.set_prev_sibling(None);
+        self.set_next_sibling(None);
+    }
+}
+impl<Value> std::clone::Clone for ArcNode<Value> {
+    fn clone(&self) -> Self { ArcNode(self.0.clone()) }
+}
+impl<Value: std::fmt::Debug> std::fmt::Debug for ArcNode<Value> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        // Note: The subtree is walked using the links (instead of recursion) to support deep trees.
+        // The lock of the tree is held throughout, so other threads cannot change the subtree.
+        self.read(|| {
+            let mut cur = self.clone();
+            loop {
+                cur.value().fmt(f)?;
+                if let Some(c) = cur.first_child_link() {
+                    f.write_str(" [")?;
+                    cur = c;
+                    continue;
+                }
+                loop {
+                    if cur.ptr_eq(self) {
+                        return Ok(());
+                    }
+                    if let Some(s) = cur.next_sibling_link() {
+                        cur = s;
+                        break;
+                    }
+                    cur = cur.parent_link().ok_or(std::fmt::Error)?;
+                    f.write_str("]")?;
+                }
+            }
+        })
+    }
+}
+
+/// A weak handle to an `ArcNode`. Weak handles do not keep the node alive.
+pub struct WeakArcNode<Value>(Weak<NodeData<Value>>);
+impl<Value> WeakArcNode<Value> {
+    /// Creates a weak handle that does not refer to any node. Calling `upgrade` on it always
+    /// returns `None`.
+    pub fn new() -> Self { WeakArcNode(Weak::new()) }
+
+    /// Returns the node if it is still alive.
+    pub fn upgrade(&self) -> Option<ArcNode<Value>> { self.0.upgrade().map(ArcNode) }
+
+    /// Returns true if both handles refer to the same node.
+    pub fn ptr_eq(&self, other: &Self) -> bool { self.0.ptr_eq(&other.0) }
+
+    /// Returns true if this handle refers to the specified node.
+    pub fn is_node(&self, node: &ArcNode<Value>) -> bool { std::ptr::eq(self.0.as_ptr(), Arc::as_ptr(&node.0)) }
+
+    pub fn strong_count(&self) -> usize { self.0.strong_count() }
+    pub fn weak_count(&self) -> usize { self.0.weak_count() }
+}
+impl<Value> std::clone::Clone for WeakArcNode<Value> {
+    fn clone(&self) -> Self { WeakArcNode(self.0.clone()) }
+}
+impl<Value> std::default::Default for WeakArcNode<Value> {
+    fn default() -> Self { WeakArcNode::new() }
+}
+impl<Value> std::cmp::PartialEq for WeakArcNode<Value> {
+    fn eq(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+impl<Value> std::cmp::Eq for WeakArcNode<Value> {}
+impl<Value> std::hash::Hash for WeakArcNode<Value> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) { self.0.as_ptr().hash(state) }
+}
+impl<Value> std::fmt::Debug for WeakArcNode<Value> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> { f.write_str("(WeakArcNode)") }
+}
+
+pub struct ArcNodeSiblingIter<Value> {
+    iter: std::vec::IntoIter<ArcNode<Value>>,
+}
+impl<Value> std::iter::Iterator for ArcNodeSiblingIter<Value> {
+    type Item = ArcNode<Value>;
+
+    fn next(&mut self) -> Option<ArcNode<Value>> { self.iter.next() }
+}
@@ -0,0 +1,259 @@
+//! Helpers for testing code that builds or edits trees.
+//!
+//! `Model` is a plain nested representation of a tree that serves as the reference model. It can
+//! be converted to and from `arena::Tree` and `RcNode`. An `Op` is a single structural change
+//! that can be applied to all three representations, so the results of a sequence of operations
+//! can be compared (see `check_ops`).
+//!
+//! Random models, trees and operations are available behind the optional `arbitrary` (`Arbitrary`
+//! impls for `Model`, `Op`, `arena::Tree` and `RcNode`) and `proptest` (see `testing::strategy`)
+//! features.
+
+use arena::{IndexType, Node, Storage, Tree};
+use rctree::RcNode;
+use std;
+use walk::Links;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+
+/// A node value along with the (nested) child nodes.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Model<T> {
+    pub value: T,
+    pub children: Vec<Model<T>>,
+}
+impl<T> Model<T> {
+    pub fn new(value: T, children: Vec<Model<T>>) -> Self { Model { value, children } }
+    pub fn leaf(value: T) -> Self { Model { value, children: Vec::new() } }
+
+    /// Returns the number of nodes (including this one).
+    pub fn len(&self) -> usize { 1 + self.children.iter().map(|c| c.len()).sum::<usize>() }
+    /// Always false, a model contains at least its root.
+    pub fn is_empty(&self) -> bool { false }
+
+    /// Returns the values in pre-order.
+    pub fn values(&self) -> Vec<&T> {
+        let mut result = Vec::new();
+        let mut pending = vec![self];
+        while let Some(m) = pending.pop() {
+            result.push(&m.value);
+            pending.extend(m.children.iter().rev());
+        }
+        result
+    }
+
+    /// Returns the child indices leading to the node at the given pre-order position.
+    fn path_of(&self, position: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut cur = self;
+        let mut remaining = position;
+        while remaining > 0 {
+            // Skip the current node, then skip whole subtrees until the position is inside one
+            remaining -= 1;
+            let mut idx = 0;
+            while remaining >= cur.children[idx].len() {
+                remaining -= cur.children[idx].len();
+                idx += 1;
+            }
+            path.push(idx);
+            cur = &cur.children[idx];
+        }
+        path
+    }
+
+    fn at_path_mut(&mut self, path: &[usize]) -> &mut Model<T> {
+        let mut cur = self;
+        for &idx in path {
+            cur = &mut cur.children[idx];
+        }
+        cur
+    }
+
+    /// Builds the nodes of the model in the arena and returns the (detached) root.
+    pub fn to_arena<IdType: Copy + Eq, Ix: IndexType, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>) -> Node<IdType, Ix>
+    where
+        T: Clone,
+    {
+        let root = t.create_node(self.value.clone());
+        for c in &self.children {
+            let child = c.to_arena(t);
+            root.append_child(t, child);
+        }
+        root
+    }
+
+    pub fn to_rc(&self) -> RcNode<T>
+    where
+        T: Clone,
+    {
+        let root = RcNode::new(self.value.clone());
+        for c in &self.children {
+            root.append_child(&c.to_rc());
+        }
+        root
+    }
+
+    pub fn from_arena<IdType: Copy + Eq, Ix: IndexType, S: Storage<T, Ix>>(t: &Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) -> Self
+    where
+        T: Clone,
+    {
+        Model::new(root.value(t).clone(), root.children(t).map(|c| Model::from_arena(t, c)).collect())
+    }
+
+    pub fn from_rc(root: &RcNode<T>) -> Self
+    where
+        T: Clone,
+    {
+        Model::new(root.value().clone(), root.children().map(|c| Model::from_rc(&c)).collect())
+    }
+}
+
+/// A structural change to a tree. The target node is given by its pre-order position (the root is
+/// zero), taken modulo the number of nodes, so any operation can be applied to any tree.
+/// Operations that require the target to have a parent do nothing when the target is the root.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Op<T> {
+    /// Adds a new node after the last child of the target (`append_child`).
+    AppendChild(usize, T),
+    /// Adds a new node before the first child of the target (`prepend_child`).
+    PrependChild(usize, T),
+    /// Adds a new node immediately after the target (`insert_next_sibling`).
+    InsertNextSibling(usize, T),
+    /// Adds a new node immediately before the target (`insert_prev_sibling`).
+    InsertPrevSibling(usize, T),
+    /// Detaches the target along with its subtree (`remove`).
+    Remove(usize),
+    /// Detaches the children of the target (`remove_children`).
+    RemoveChildren(usize),
+}
+impl<T: Clone> Op<T> {
+    /// Returns the pre-order position of the target node (before taking it modulo the number of
+    /// nodes).
+    pub fn target(&self) -> usize {
+        match *self {
+            Op::AppendChild(n, _) | Op::PrependChild(n, _) | Op::InsertNextSibling(n, _) | Op::InsertPrevSibling(n, _) | Op::Remove(n) | Op::RemoveChildren(n) => n,
+        }
+    }
+
+    /// Returns true if the operation needs the target to have a parent.
+    fn needs_parent(&self) -> bool { matches!(*self, Op::InsertNextSibling(..) | Op::InsertPrevSibling(..) | Op::Remove(_)) }
+
+    pub fn apply_model(&self, m: &mut Model<T>) {
+        let position = self.target() % m.len();
+        if position == 0 && self.needs_parent() {
+            return;
+        }
+        let mut path = m.path_of(position);
+        match *self {
+            Op::AppendChild(_, ref v) => m.at_path_mut(&path).children.push(Model::leaf(v.clone())),
+            Op::PrependChild(_, ref v) => m.at_path_mut(&path).children.insert(0, Model::leaf(v.clone())),
+            Op::RemoveChildren(_) => m.at_path_mut(&path).children.clear(),
+            Op::InsertNextSibling(_, ref v) => {
+                let idx = path.pop().unwrap();
+                m.at_path_mut(&path).children.insert(idx + 1, Model::leaf(v.clone()));
+            }
+            Op::InsertPrevSibling(_, ref v) => {
+                let idx = path.pop().unwrap();
+                m.at_path_mut(&path).children.insert(idx, Model::leaf(v.clone()));
+            }
+            Op::Remove(_) => {
+                let idx = path.pop().unwrap();
+                m.at_path_mut(&path).children.remove(idx);
+            }
+        }
+    }
+
+    /// Applies the operation to the subtree of the given root.
+    pub fn apply_arena<IdType: Copy + Eq, Ix: IndexType, S: Storage<T, Ix>>(&self, t: &mut Tree<IdType, T, Ix, S>, root: Node<IdType, Ix>) {
+        let len = root.depth_first_search_subtree(t).count();
+        let position = self.target() % len;
+        if position == 0 && self.needs_parent() {
+            return;
+        }
+        let n = root.depth_first_search_subtree(t).nth(position).unwrap().1;
+        match *self {
+            Op::AppendChild(_, ref v) => {
+                let c = t.create_node(v.clone());
+                n.append_child(t, c);
+            }
+            Op::PrependChild(_, ref v) => {
+                let c = t.create_node(v.clone());
+                n.prepend_child(t, c);
+            }
+            Op::InsertNextSibling(_, ref v) => {
+                let c = t.create_node(v.clone());
+                n.insert_next_sibling(t, c);
+            }
+            Op::InsertPrevSibling(_, ref v) => {
+                let c = t.create_node(v.clone());
+                n.insert_prev_sibling(t, c);
+            }
+            Op::Remove(_) => n.remove(t),
+            Op::RemoveChildren(_) => n.remove_children(t),
+        }
+    }
+
+    /// Applies the operation to the subtree of the given root.
+    pub fn apply_rc(&self, root: &RcNode<T>) {
+        let len = rc_preorder(root).count();
+        let position = self.target() % len;
+        if position == 0 && self.needs_parent() {
+            return;
+        }
+        let n = rc_preorder(root).nth(position).unwrap();
+        match *self {
+            Op::AppendChild(_, ref v) => n.append_child(&RcNode::new(v.clone())),
+            Op::PrependChild(_, ref v) => n.prepend_child(&RcNode::new(v.clone())),
+            Op::InsertNextSibling(_, ref v) => n.insert_next_sibling(&RcNode::new(v.clone())),
+            Op::InsertPrevSibling(_, ref v) => n.insert_prev_sibling(&RcNode::new(v.clone())),
+            Op::Remove(_) => n.remove(),
+            Op::RemoveChildren(_) => n.remove_children(),
+        }
+    }
+}
+
+/// Walks the subtree of an `RcNode` in pre-order.
+fn rc_preorder<T>(root: &RcNode<T>) -> impl Iterator<Item = RcNode<T>> {
+    let root = root.clone();
+    let mut next = Some(root.clone());
+    std::iter::from_fn(move || {
+        let cur = next.take()?;
+        next = cur.next_preorder(Some(&root));
+        Some(cur)
+    })
+}
+
+/// Applies the operations to the model, to an `arena::Tree` and to an `RcNode` tree built from
+/// the initial model. After every step, the trees are compared with the model and their links
+/// are verified. Returns the final model, or a description of the first difference.
+pub fn check_ops<T: Clone + PartialEq + std::fmt::Debug>(initial: &Model<T>, ops: &[Op<T>]) -> Result<Model<T>, String> {
+    let mut model = initial.clone();
+    let mut t: Tree<(), T> = Tree::new(());
+    let root = model.to_arena(&mut t);
+    let rc_root = model.to_rc();
+    for (step, op) in ops.iter().enumerate() {
+        op.apply_model(&mut model);
+        op.apply_arena(&mut t, root);
+        op.apply_rc(&rc_root);
+
+        let arena_model = Model::from_arena(&t, root);
+        if arena_model != model {
+            return Err(format!("arena tree differs after step {} ({:?}): expected {:?}, found {:?}", step, op, model, arena_model));
+        }
+        let rc_model = Model::from_rc(&rc_root);
+        if rc_model != model {
+            return Err(format!("RcNode tree differs after step {} ({:?}): expected {:?}, found {:?}", step, op, model, rc_model));
+        }
+        let report = t.check_invariants();
+        if !report.is_ok() {
+            return Err(format!("arena tree links are broken after step {} ({:?}): {:?}", step, op, report.violations()));
+        }
+        if !rc_root.check_subtree().is_ok() {
+            return Err(format!("RcNode tree links are broken after step {} ({:?})", step, op));
+        }
+    }
+    Ok(model)
+}
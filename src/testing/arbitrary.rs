@@ -0,0 +1,56 @@
+use super::{Model, Op};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use arena::{IndexType, Storage, Tree};
+use rctree::RcNode;
+
+/// The bounds used by the `Arbitrary` impls of the trees.
+const MAX_DEPTH: usize = 4;
+const MAX_CHILDREN: usize = 4;
+
+impl<'a, T: Arbitrary<'a>> Model<T> {
+    /// Generates a model with at most `max_depth` levels below the root and at most
+    /// `max_children` children per node.
+    pub fn arbitrary_bounded(u: &mut Unstructured<'a>, max_depth: usize, max_children: usize) -> Result<Self> {
+        let value = T::arbitrary(u)?;
+        let count = if max_depth == 0 { 0 } else { u.int_in_range(0..=max_children)? };
+        let mut children = Vec::with_capacity(count);
+        for _ in 0..count {
+            children.push(Model::arbitrary_bounded(u, max_depth - 1, max_children)?);
+        }
+        Ok(Model::new(value, children))
+    }
+}
+impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for Model<T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> { Model::arbitrary_bounded(u, MAX_DEPTH, MAX_CHILDREN) }
+}
+
+impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for Op<T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let target = usize::arbitrary(u)?;
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Op::AppendChild(target, T::arbitrary(u)?),
+            1 => Op::PrependChild(target, T::arbitrary(u)?),
+            2 => Op::InsertNextSibling(target, T::arbitrary(u)?),
+            3 => Op::InsertPrevSibling(target, T::arbitrary(u)?),
+            4 => Op::Remove(target),
+            _ => Op::RemoveChildren(target),
+        })
+    }
+}
+
+impl<'a, T: Arbitrary<'a> + Clone> Arbitrary<'a> for RcNode<T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> { Ok(Model::arbitrary(u)?.to_rc()) }
+}
+
+/// Generates a forest: up to `MAX_CHILDREN` roots (see `Tree::push_root`), each with a bounded
+/// subtree.
+impl<'a, IdType: Arbitrary<'a> + Copy + Eq, T: Arbitrary<'a> + Clone, Ix: IndexType, S: Storage<T, Ix>> Arbitrary<'a> for Tree<IdType, T, Ix, S> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut t = Tree::new(IdType::arbitrary(u)?);
+        for _ in 0..u.int_in_range(0..=MAX_CHILDREN)? {
+            let root = Model::arbitrary(u)?.to_arena(&mut t);
+            t.push_root(root);
+        }
+        Ok(t)
+    }
+}
@@ -0,0 +1,82 @@
+//! `proptest` strategies for models, trees and operations. The trees are limited to `max_depth`
+//! levels below the root and `max_children` children per node.
+
+use super::{Model, Op};
+use arena::{Node, Tree};
+use proptest::collection::vec;
+use proptest::prelude::{any, BoxedStrategy, Strategy};
+use proptest::prop_oneof;
+use rctree::RcNode;
+use std;
+
+pub fn model<S>(value: S, max_depth: usize, max_children: usize) -> BoxedStrategy<Model<S::Value>>
+where
+    S: Strategy + Clone + 'static,
+    S::Value: Clone + std::fmt::Debug,
+{
+    if max_depth == 0 {
+        return value.prop_map(Model::leaf).boxed();
+    }
+    let children = vec(model(value.clone(), max_depth - 1, max_children), 0..=max_children);
+    (value, children).prop_map(|(value, children)| Model::new(value, children)).boxed()
+}
+
+/// An arena tree along with its root. Shown as the `Model` of the root in test failures.
+pub struct ArenaTree<T> {
+    pub tree: Tree<(), T>,
+    pub root: Node<()>,
+}
+impl<T: Clone + std::fmt::Debug> std::fmt::Debug for ArenaTree<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { Model::from_arena(&self.tree, self.root).fmt(f) }
+}
+
+/// Generates an arena tree with a single root (see `Tree::push_root`).
+pub fn arena_tree<S>(value: S, max_depth: usize, max_children: usize) -> BoxedStrategy<ArenaTree<S::Value>>
+where
+    S: Strategy + Clone + 'static,
+    S::Value: Clone + std::fmt::Debug,
+{
+    model(value, max_depth, max_children)
+        .prop_map(|m| {
+            let mut tree = Tree::new(());
+            let root = m.to_arena(&mut tree);
+            tree.push_root(root);
+            ArenaTree { tree, root }
+        })
+        .boxed()
+}
+
+pub fn rc_tree<S>(value: S, max_depth: usize, max_children: usize) -> BoxedStrategy<RcNode<S::Value>>
+where
+    S: Strategy + Clone + 'static,
+    S::Value: Clone + std::fmt::Debug,
+{
+    model(value, max_depth, max_children).prop_map(|m| m.to_rc()).boxed()
+}
+
+pub fn op<S>(value: S) -> BoxedStrategy<Op<S::Value>>
+where
+    S: Strategy + Clone + 'static,
+    S::Value: Clone + std::fmt::Debug,
+{
+    let target = any::<usize>();
+    prop_oneof![
+        (target, value.clone()).prop_map(|(n, v)| Op::AppendChild(n, v)),
+        (target, value.clone()).prop_map(|(n, v)| Op::PrependChild(n, v)),
+        (target, value.clone()).prop_map(|(n, v)| Op::InsertNextSibling(n, v)),
+        (target, value).prop_map(|(n, v)| Op::InsertPrevSibling(n, v)),
+        target.prop_map(Op::Remove),
+        target.prop_map(Op::RemoveChildren),
+    ]
+    .boxed()
+}
+
+/// Generates up to `max_len` operations.
+pub fn ops<S>(value: S, max_len: usize) -> BoxedStrategy<Vec<Op<S::Value>>>
+where
+    S: Strategy + Clone + 'static,
+    S::Value: Clone + std::fmt::Debug,
+{
+    vec(op(value), 0..=max_len).boxed()
+}
+
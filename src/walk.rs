@@ -0,0 +1,60 @@
+use arena::{IndexType, NodeRef, Storage};
+use rctree::RcNode;
+
+/// The links needed to walk a subtree in pre-order without recursion.
+pub(crate) trait Links: Clone {
+    fn parent(&self) -> Option<Self>;
+    fn first_child(&self) -> Option<Self>;
+    fn next_sibling(&self) -> Option<Self>;
+    /// Returns true if both handles refer to the same node.
+    fn is_same(&self, other: &Self) -> bool;
+
+    /// Returns the node following the subtree of this node in pre-order, without leaving the
+    /// subtree of `root` (if given).
+    fn next_in_subtree(&self, root: Option<&Self>) -> Option<Self> {
+        let mut cur = self.clone();
+        loop {
+            if root.is_some_and(|r| r.is_same(&cur)) {
+                return None;
+            }
+            if let Some(s) = cur.next_sibling() {
+                return Some(s);
+            }
+            cur = cur.parent()?;
+        }
+    }
+
+    /// Returns the node following this node in pre-order, without leaving the subtree of `root`
+    /// (if given).
+    fn next_preorder(&self, root: Option<&Self>) -> Option<Self> { self.first_child().or_else(|| self.next_in_subtree(root)) }
+}
+
+/// The navigation needed to walk a subtree, implemented for the node types that can be exported.
+pub(crate) trait WalkNode: Links {
+    type Value;
+
+    fn value(&self) -> &Self::Value;
+}
+
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> Links for NodeRef<'a, IdType, T, Ix, S> {
+    fn parent(&self) -> Option<Self> { NodeRef::parent(self) }
+    fn first_child(&self) -> Option<Self> { NodeRef::first_child(self) }
+    fn next_sibling(&self) -> Option<Self> { NodeRef::next_sibling(self) }
+    fn is_same(&self, other: &Self) -> bool { self == other }
+}
+impl<'a, IdType: Copy + Eq + 'a, T: 'a, Ix: IndexType, S: Storage<T, Ix> + 'a> WalkNode for NodeRef<'a, IdType, T, Ix, S> {
+    type Value = T;
+
+    fn value(&self) -> &T { NodeRef::value(self) }
+}
+impl<Value> Links for RcNode<Value> {
+    fn parent(&self) -> Option<Self> { RcNode::parent(self) }
+    fn first_child(&self) -> Option<Self> { RcNode::first_child(self) }
+    fn next_sibling(&self) -> Option<Self> { RcNode::next_sibling(self) }
+    fn is_same(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+impl<Value> WalkNode for RcNode<Value> {
+    type Value = Value;
+
+    fn value(&self) -> &Value { RcNode::value(self) }
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "arbitrary")]
+
+extern crate arbitrary;
+extern crate trees;
+
+use arbitrary::{Arbitrary, Unstructured};
+use trees::arena::Tree;
+use trees::rctree::RcNode;
+use trees::testing::{check_ops, Model, Op};
+
+/// Deterministic pseudo-random input bytes.
+fn bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn ops_match_model() {
+    for seed in 0..200 {
+        let data = bytes(seed, 512);
+        let mut u = Unstructured::new(&data);
+        let initial = Model::<u8>::arbitrary(&mut u).unwrap();
+        let ops = Vec::<Op<u8>>::arbitrary(&mut u).unwrap();
+        if let Err(e) = check_ops(&initial, &ops) {
+            panic!("seed {}: {}", seed, e);
+        }
+    }
+}
+
+#[test]
+fn generated_trees() {
+    for seed in 0..50 {
+        let data = bytes(seed, 256);
+        let mut u = Unstructured::new(&data);
+        let t = Tree::<u8, u16>::arbitrary(&mut u).unwrap();
+        assert!(t.check_invariants().is_ok());
+        assert!(t.depth_first_search().all(|(depth, _)| depth <= 4));
+        assert_eq!(t.detached_roots().count(), 0);
+
+        let rc = RcNode::<u16>::arbitrary(&mut u).unwrap();
+        assert!(rc.check_subtree().is_ok());
+    }
+}
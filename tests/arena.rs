@@ -0,0 +1,530 @@
+extern crate trees;
+
+use std::cmp::Ordering;
+use trees::arena::{CapacityError, InterleavedStorage, LcaIndex, Node, OrderIndex, SplitStorage, Storage, Tree};
+use trees::{InsertPosition, RetainMode};
+
+type TestTree<S> = Tree<(), &'static str, u32, S>;
+
+fn sample() -> (Tree<(), &'static str>, Node<()>) { sample_in() }
+
+/// Builds `a [b [d e] c]` and returns the tree along with the root.
+fn sample_in<S: Storage<&'static str, u32>>() -> (TestTree<S>, Node<()>) {
+    let mut t = Tree::new(());
+    let a = t.create_node("a");
+    let b = a.append_child_value(&mut t, "b");
+    a.append_child_value(&mut t, "c");
+    b.append_child_value(&mut t, "d");
+    b.append_child_value(&mut t, "e");
+    (t, a)
+}
+
+fn preorder<S: Storage<&'static str, u32>>(t: &TestTree<S>, root: Node<()>) -> Vec<&'static str> {
+    let mut c = t.cursor(root);
+    let mut out = vec![*c.value()];
+    while c.goto_next_preorder() {
+        out.push(*c.value());
+    }
+    out
+}
+
+#[test]
+fn cursor_navigation() {
+    let (t, a) = sample();
+    let mut c = t.cursor(a);
+    assert!(!c.goto_parent());
+    assert!(!c.goto_next_sibling());
+    assert_eq!(*c.value(), "a");
+
+    assert!(c.goto_last_child());
+    assert_eq!(*c.value(), "c");
+    assert!(!c.goto_first_child());
+    assert!(c.goto_prev_sibling());
+    assert_eq!(*c.value(), "b");
+    assert!(!c.goto_prev_sibling());
+    assert!(c.goto_first_child());
+    assert!(c.goto_next_sibling());
+    assert_eq!(*c.value(), "e");
+    assert!(c.goto_parent());
+    assert_eq!(*c.value(), "b");
+
+    assert_eq!(preorder(&t, a), ["a", "b", "d", "e", "c"]);
+}
+
+#[test]
+fn cursor_preorder_bounds() {
+    let (mut t, a) = sample();
+    let f = t.create_node("f");
+    t.push_root(a);
+    t.push_root(f);
+    // The walk stays within the subtree of the starting node
+    assert_eq!(preorder(&t, a), ["a", "b", "d", "e", "c"]);
+    let b = t.get(a).first_child().unwrap().node();
+    assert_eq!(preorder(&t, b), ["b", "d", "e"]);
+
+    // Moving to a sibling of the starting node moves the bound along with it
+    let mut c = t.cursor(b);
+    assert!(c.goto_next_sibling());
+    assert!(!c.goto_next_preorder());
+    assert!(c.goto_parent());
+    c.goto_last_child();
+    assert!(!c.goto_next_preorder());
+    c.reset(a);
+    assert!(c.goto_next_sibling());
+    assert_eq!(*c.value(), "f");
+}
+
+#[test]
+fn cursor_edits() {
+    let (mut t, a) = sample();
+    {
+        let mut c = t.cursor_mut(a);
+        c.goto_first_child();
+        c.insert_after("x");
+        c.insert_before("w");
+        c.goto_first_child();
+        *c.value_mut() = "D";
+        c.goto_next_sibling();
+        c.insert_child("f");
+    }
+    assert_eq!(preorder(&t, a), ["a", "w", "b", "D", "e", "f", "x", "c"]);
+
+    let mut c = t.cursor_mut(a);
+    c.goto_first_child();
+    c.goto_next_sibling();
+    // Removing "b" skips its subtree and continues with the following node
+    assert!(c.remove_and_advance());
+    assert_eq!(*c.value(), "x");
+    c.goto_next_sibling();
+    // Nothing follows "c", so the cursor falls back to the parent
+    assert!(!c.remove_and_advance());
+    assert_eq!(c.node(), a);
+    assert_eq!(preorder(&t, a), ["a", "w", "x"]);
+}
+
+#[test]
+fn node_refs() {
+    let (t, a) = sample();
+    let b = t.get(a).first_child().unwrap();
+    assert_eq!(*b.value(), "b");
+    assert_eq!(*b.next_sibling().unwrap().value(), "c");
+    assert_eq!(b.parent().unwrap().node(), a);
+    assert_eq!(b.last_child().unwrap().root(), t.get(a));
+    assert_eq!(b.children().map(|n| *n.value()).collect::<Vec<_>>(), ["d", "e"]);
+    // A search from "b" does not continue into its sibling "c"
+    assert_eq!(b.descendants().map(|n| *n.value()).collect::<Vec<_>>(), ["b", "d", "e"]);
+    let e = b.last_child().unwrap();
+    assert_eq!(e.ancestors().map(|n| *n.value()).collect::<Vec<_>>(), ["b", "a"]);
+    assert_eq!(t[e.node()], "e");
+}
+
+#[test]
+fn node_mut_edits() {
+    let (mut t, a) = sample();
+    {
+        let mut root = t.get_mut(a);
+        let mut c = root.last_child().unwrap();
+        *c.value_mut() = "C";
+        c.append_child_value("f").insert_prev_sibling_value("g");
+        c.insert_next_sibling_value("h");
+        root.first_child().unwrap().remove();
+    }
+    assert_eq!(preorder(&t, a), ["a", "C", "g", "f", "h"]);
+    t[a] = "A";
+    assert_eq!(*t.get(a).value(), "A");
+}
+
+#[test]
+fn many_values_mut() {
+    let (mut t, a) = sample();
+    let b = t.get(a).first_child().unwrap().node();
+    let c = t.get(a).last_child().unwrap().node();
+    {
+        let [va, vc] = t.get_many_mut([a, c]).unwrap();
+        std::mem::swap(va, vc);
+    }
+    assert_eq!((t[a], t[c]), ("c", "a"));
+    assert!(t.get_many_mut([a, b, a]).is_none());
+
+    let mut seen = Vec::new();
+    let mut iter = b.values_mut(&mut t);
+    while let Some((depth, _, value)) = iter.next_value() {
+        seen.push((depth, *value));
+        *value = "x";
+    }
+    assert_eq!(seen, [(0, "b"), (1, "d"), (1, "e")]);
+    assert_eq!(preorder(&t, a), ["c", "x", "x", "x", "a"]);
+}
+
+#[test]
+fn structural_edits() {
+    structural_edits_in::<InterleavedStorage<_, _>>();
+    structural_edits_in::<SplitStorage<_, _>>();
+}
+
+fn structural_edits_in<S: Storage<&'static str, u32>>() {
+    let (mut t, a) = sample_in::<S>();
+    let b = a.first_child(&t).unwrap();
+    let c = a.last_child(&t).unwrap();
+    let d = b.first_child(&t).unwrap();
+    b.unwrap(&mut t);
+    assert_eq!(preorder(&t, a), ["a", "d", "e", "c"]);
+    let w = d.wrap_with_value(&mut t, "w");
+    assert_eq!(w.first_child(&t), Some(d));
+    assert_eq!(preorder(&t, a), ["a", "w", "d", "e", "c"]);
+    assert_eq!(c.replace_with(&mut t, b), b);
+    assert_eq!(preorder(&t, a), ["a", "w", "d", "e", "b"]);
+    Node::swap(&mut t, w, c);
+    assert_eq!(preorder(&t, a), ["a", "c", "e", "b"]);
+    assert_eq!(preorder(&t, w), ["w", "d"]);
+    assert!(w.parent(&t).is_none());
+    assert!(t.check_invariants().is_ok());
+
+    // A descendant can become the new parent, it is moved out of the subtree first
+    let (mut t, a) = sample_in::<S>();
+    let b = a.first_child(&t).unwrap();
+    let d = b.first_child(&t).unwrap();
+    b.wrap_with(&mut t, d);
+    assert_eq!(preorder(&t, a), ["a", "d", "b", "e", "c"]);
+    assert!(t.check_invariants().is_ok());
+}
+
+#[test]
+#[should_panic(expected = "Unable to wrap a node with itself or one of its ancestors")]
+fn wrap_with_ancestor_panics() {
+    let (mut t, a) = sample();
+    let d = a.first_child(&t).unwrap().first_child(&t).unwrap();
+    d.wrap_with(&mut t, a);
+}
+
+#[test]
+#[should_panic(expected = "Unable to replace a node with one of its ancestors")]
+fn replace_with_ancestor_panics() {
+    let (mut t, a) = sample();
+    let b = a.first_child(&t).unwrap();
+    let d = b.first_child(&t).unwrap();
+    d.replace_with(&mut t, b);
+}
+
+#[test]
+fn sibling_ranges() {
+    sibling_ranges_in::<InterleavedStorage<_, _>>();
+    sibling_ranges_in::<SplitStorage<_, _>>();
+}
+
+fn sibling_ranges_in<S: Storage<&'static str, u32>>() {
+    let (mut t, a) = sample_in::<S>();
+    let b = a.first_child(&t).unwrap();
+    let c = a.last_child(&t).unwrap();
+    let (d, e) = (b.first_child(&t).unwrap(), b.last_child(&t).unwrap());
+    let x = a.append_child_value(&mut t, "x");
+    let y = a.append_child_value(&mut t, "y");
+    let check = |t: &TestTree<S>, expected: &[&str]| {
+        assert_eq!(preorder(t, a), expected);
+        assert!(t.check_invariants().is_ok());
+    };
+
+    // A range from the middle, inserted before the first child
+    Node::move_sibling_range(&mut t, c, x, b, InsertPosition::First);
+    check(&t, &["a", "b", "c", "x", "d", "e", "y"]);
+    // A range ending with the last child, inserted before a sibling of its parent
+    Node::move_sibling_range(&mut t, d, e, a, InsertPosition::Before(y));
+    check(&t, &["a", "b", "c", "x", "d", "e", "y"]);
+    assert_eq!(a.children(&t).collect::<Vec<_>>(), [b, d, e, y]);
+    // The first child, moved into an empty child list
+    Node::move_sibling_range(&mut t, b, b, y, InsertPosition::Last);
+    check(&t, &["a", "d", "e", "y", "b", "c", "x"]);
+    // All children of a node, inserted after a node
+    Node::move_sibling_range(&mut t, c, x, a, InsertPosition::After(d));
+    check(&t, &["a", "d", "c", "x", "e", "y", "b"]);
+    assert_eq!(b.first_child(&t), None);
+    assert_eq!(b.last_child(&t), None);
+
+    let s = d.split_children_after_value(&mut t, "s");
+    check(&t, &["a", "d"]);
+    assert_eq!(preorder(&t, s), ["s", "c", "x", "e", "y", "b"]);
+    assert_eq!(a.last_child(&t), Some(d));
+    // Nothing follows the last child
+    let empty = d.split_children_after_value(&mut t, "empty");
+    assert_eq!(empty.first_child(&t), None);
+
+    // Splitting the roots of the tree
+    t.push_root(a);
+    t.push_root(s);
+    t.push_root(empty);
+    let r = a.split_children_after_value(&mut t, "r");
+    assert_eq!(t.roots().collect::<Vec<_>>(), [a]);
+    assert_eq!(r.children(&t).collect::<Vec<_>>(), [s, empty]);
+    assert!(t.check_invariants().is_ok());
+}
+
+#[test]
+#[should_panic(expected = "Unable to move a sibling range underneath itself")]
+fn sibling_range_into_itself_panics() {
+    let (mut t, a) = sample();
+    let b = a.first_child(&t).unwrap();
+    let (c, d) = (a.last_child(&t).unwrap(), b.first_child(&t).unwrap());
+    Node::move_sibling_range(&mut t, b, c, d, InsertPosition::Last);
+}
+
+/// Fills a tree using `u16` indexes to its capacity.
+fn full_tree() -> Tree<(), (), u16> {
+    let mut t = Tree::new(());
+    assert_eq!(t.max_nodes(), u16::MAX as usize);
+    for _ in 0..t.max_nodes() {
+        t.create_node(());
+    }
+    t
+}
+
+#[test]
+fn index_capacity() {
+    let mut t = full_tree();
+    let err: CapacityError = t.try_create_node(()).unwrap_err();
+    assert_eq!(err.max_nodes(), u16::MAX as usize);
+    assert_eq!(t.all_nodes().count(), u16::MAX as usize);
+    // The nodes that exist can still be linked
+    let last = t.all_nodes().last().unwrap();
+    t.push_root(last);
+    assert_eq!(t.roots().collect::<Vec<_>>(), [last]);
+}
+
+#[test]
+#[should_panic(expected = "The tree has reached its capacity of 65535 nodes")]
+fn create_node_beyond_capacity_panics() {
+    let mut t = full_tree();
+    t.create_node(());
+}
+
+#[test]
+fn node_size() {
+    use std::mem::size_of;
+    assert_eq!(size_of::<Option<Node<(), u32>>>(), size_of::<Node<(), u32>>());
+    assert_eq!(size_of::<Node<(), u32>>(), 4);
+    assert_eq!(size_of::<Option<Node<(), u16>>>(), 2);
+}
+
+#[test]
+fn lowest_common_ancestors() {
+    // Random tree using a small LCG, each node attached to an earlier node
+    let mut t: Tree<(), usize> = Tree::new(());
+    let mut nodes = vec![t.create_node(0)];
+    let mut seed: u64 = 42;
+    for i in 1..300 {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let parent = nodes[(seed >> 33) as usize % nodes.len()];
+        nodes.push(parent.append_child_value(&mut t, i));
+    }
+    let index = LcaIndex::new(&t, nodes[0]);
+    for (i, &a) in nodes.iter().enumerate().step_by(7) {
+        for &b in nodes.iter().skip(i % 5).step_by(11) {
+            let lca = Node::lowest_common_ancestor(&t, a, b).unwrap();
+            assert!(lca == a || lca.is_ancestor_of(&t, a));
+            assert!(lca == b || lca.is_ancestor_of(&t, b));
+            assert_eq!(index.lowest_common_ancestor(a, b), Some(lca));
+            assert_eq!(index.distance(a, b), Node::distance(&t, a, b));
+            assert_eq!(index.is_ancestor_of(a, b), a.is_ancestor_of(&t, b));
+            assert_eq!(index.depth(a), Some(a.depth(&t)));
+        }
+    }
+
+    // Nodes outside of the indexed subtree (or created later) are not found
+    let sub = LcaIndex::new(&t, nodes[1]);
+    assert!(!sub.contains(nodes[0]));
+    assert_eq!(sub.lowest_common_ancestor(nodes[0], nodes[1]), None);
+    let detached = t.create_node(1000);
+    assert_eq!(index.lowest_common_ancestor(detached, nodes[0]), None);
+    assert_eq!(Node::lowest_common_ancestor(&t, detached, nodes[0]), None);
+}
+
+#[test]
+fn document_order() {
+    let (t, a) = sample();
+    let mut nodes: Vec<Node<()>> = a.depth_first_search_subtree(&t).map(|(_, n)| n).collect();
+    let expected = nodes.clone();
+    for &x in &expected {
+        for &y in &expected {
+            let by_path = x.path(&t).cmp(&y.path(&t));
+            assert_eq!(Node::compare_document_order(&t, x, y), by_path);
+        }
+    }
+
+    let index = OrderIndex::new(&t, a);
+    nodes.reverse();
+    nodes.swap(1, 3);
+    index.sort(&mut nodes);
+    assert_eq!(nodes, expected);
+    let (b, d) = (expected[1], expected[2]);
+    assert_eq!(index.pre_order(a), Some(0));
+    assert_eq!(index.post_order(a), Some(4));
+    assert_eq!(index.post_order(d), Some(0));
+    assert!(index.is_ancestor_of(b, d));
+    assert!(!index.is_ancestor_of(d, b));
+    assert_eq!(index.compare(expected[4], b), Some(Ordering::Greater));
+}
+
+#[test]
+fn forest_roots() {
+    forest_roots_in::<InterleavedStorage<_, _>>();
+    forest_roots_in::<SplitStorage<_, _>>();
+}
+
+fn forest_roots_in<S: Storage<&'static str, u32>>() {
+    let (mut t, a) = sample_in::<S>();
+    assert_eq!(t.roots().count(), 0);
+    assert!(!a.is_root(&t));
+
+    t.push_root(a);
+    let z = t.push_root_value("z");
+    let y = a.insert_next_sibling_value(&mut t, "y");
+    let x = a.insert_prev_sibling_value(&mut t, "x");
+    let roots: Vec<_> = t.roots().map(|n| *n.value(&t)).collect();
+    assert_eq!(roots, ["x", "a", "y", "z"]);
+    assert!(a.is_root(&t) && z.is_root(&t));
+    assert_eq!(t.first_root_node(), Some(x));
+
+    let all: Vec<_> = t.depth_first_search().map(|(depth, n)| (depth, *n.value(&t))).collect();
+    assert_eq!(all, [(0, "x"), (0, "a"), (1, "b"), (2, "d"), (2, "e"), (1, "c"), (0, "y"), (0, "z")]);
+    // Searching from a root continues with the following roots, unless limited to its subtree
+    let from_a: Vec<_> = a.depth_first_search(&t).map(|(depth, n)| (depth, *n.value(&t))).collect();
+    assert_eq!(from_a, all[1..]);
+    assert_eq!(a.depth_first_search_subtree(&t).count(), 5);
+
+    x.remove(&mut t);
+    z.remove(&mut t);
+    assert!(!x.is_root(&t));
+    let roots: Vec<_> = t.roots().map(|n| *n.value(&t)).collect();
+    assert_eq!(roots, ["a", "y"]);
+
+    // Moving a root underneath another node takes it out of the list of roots
+    a.append_child(&mut t, y);
+    assert_eq!(t.roots().collect::<Vec<_>>(), [a]);
+    Node::swap(&mut t, a, z);
+    assert_eq!(t.roots().collect::<Vec<_>>(), [z]);
+    assert!(!a.is_root(&t));
+}
+
+#[test]
+#[should_panic]
+fn detached_sibling_insert_panics() {
+    let (mut t, a) = sample();
+    a.insert_next_sibling_value(&mut t, "x");
+}
+
+#[test]
+fn detached_nodes() {
+    detached_nodes_in::<InterleavedStorage<_, _>>();
+    detached_nodes_in::<SplitStorage<_, _>>();
+}
+
+fn detached_nodes_in<S: Storage<&'static str, u32>>() {
+    // Without explicit roots, the root of the first node counts as attached
+    let (mut t, a) = sample_in::<S>();
+    let b = a.first_child(&t).unwrap();
+    let d = b.first_child(&t).unwrap();
+    let orphan = t.create_node("orphan");
+    assert!(!a.is_detached(&t) && !d.is_detached(&t));
+    assert!(orphan.is_detached(&t));
+    assert_eq!(t.detached_roots().collect::<Vec<_>>(), [orphan]);
+
+    b.remove(&mut t);
+    assert!(b.is_detached(&t) && d.is_detached(&t));
+    assert_eq!(t.detached_roots().collect::<Vec<_>>(), [b, orphan]);
+    assert_eq!(t.find_first(|n| *n.value(&t) == "d"), Some(d));
+    assert_eq!(t.find_first_attached(|n| *n.value(&t) == "d"), None);
+    // The subtree of "b" is detached, so it has no attached nodes
+    assert_eq!(t.attached_nodes(b).count(), 0);
+    assert_eq!(t.attached_nodes(d).count(), 0);
+    let values: Vec<_> = t.attached_nodes(a).map(|n| *n.value(&t)).collect();
+    assert_eq!(values, ["a", "c"]);
+
+    // With explicit roots, only nodes underneath them are attached
+    t.push_root(orphan);
+    assert!(a.is_detached(&t) && !orphan.is_detached(&t));
+    assert_eq!(t.detached_roots().collect::<Vec<_>>(), [a, b]);
+    orphan.append_child(&mut t, b);
+    assert_eq!(t.find_first_attached(|n| *n.value(&t) == "d"), Some(d));
+    assert_eq!(t.find_first_attached(|n| *n.value(&t) == "c"), None);
+    let values: Vec<_> = t.attached_nodes(b).map(|n| *n.value(&t)).collect();
+    assert_eq!(values, ["b", "d", "e"]);
+    // The search follows the document order rather than the order of creation
+    let x = orphan.prepend_child_value(&mut t, "x");
+    assert_eq!(t.find_first_attached(|n| n.value(&t).len() == 1), Some(x));
+}
+
+#[test]
+fn invariants_hold_after_edits() {
+    invariants_hold_after_edits_in::<InterleavedStorage<_, _>>();
+    invariants_hold_after_edits_in::<SplitStorage<_, _>>();
+}
+
+fn invariants_hold_after_edits_in<S: Storage<&'static str, u32>>() {
+    let (mut t, a) = sample_in::<S>();
+    let b = a.first_child(&t).unwrap();
+    let c = a.last_child(&t).unwrap();
+    t.push_root(a);
+    t.push_root_value("z");
+    Node::swap(&mut t, b, c);
+    b.wrap_with_value(&mut t, "w");
+    c.unwrap(&mut t);
+    let report = t.check_invariants();
+    assert!(report.is_ok(), "{:?}", report.violations());
+    assert_eq!(report.checked(), t.all_nodes().count());
+}
+
+#[test]
+#[cfg(not(feature = "debug-checks"))]
+fn invariant_violations() {
+    // Appending an ancestor is not checked by the tree, so it creates a loop
+    let (mut t, a) = sample();
+    let b = a.first_child(&t).unwrap();
+    b.append_child(&mut t, a);
+    let report = t.check_invariants();
+    assert!(!report.is_ok());
+    assert!(report.violations().iter().any(|v| v.kind() == trees::ViolationKind::Cycle));
+}
+
+#[test]
+#[cfg(feature = "debug-checks")]
+#[should_panic(expected = "Tree invariants violated after append_child")]
+fn debug_checks_catch_violations() {
+    let (mut t, a) = sample();
+    let b = a.first_child(&t).unwrap();
+    b.append_child(&mut t, a);
+}
+
+#[test]
+fn retain_removing_subtrees() {
+    let (mut t, a) = sample();
+    let c = a.last_child(&t).unwrap();
+    c.append_child_value(&mut t, "f");
+    // Rejecting "b" drops "d" and "e" without testing them
+    let mut tested = Vec::new();
+    a.retain_descendants(&mut t, RetainMode::RemoveSubtree, |t, n| {
+        tested.push(*n.value(t));
+        *n.value(t) != "b" && *n.value(t) != "f"
+    });
+    assert_eq!(tested, ["b", "c", "f"]);
+    assert_eq!(preorder(&t, a), ["a", "c"]);
+    assert!(t.check_invariants().is_ok());
+}
+
+#[test]
+fn retain_hoisting_children() {
+    let (mut t, a) = sample();
+    let e = a.first_child(&t).unwrap().last_child(&t).unwrap();
+    e.append_child_value(&mut t, "f");
+    // The hoisted children of "b" are tested in their new position, so "e" is hoisted as well
+    let mut tested = Vec::new();
+    a.retain_descendants(&mut t, RetainMode::HoistChildren, |t, n| {
+        tested.push(*n.value(t));
+        *n.value(t) != "b" && *n.value(t) != "e"
+    });
+    assert_eq!(tested, ["b", "d", "e", "f", "c"]);
+    assert_eq!(preorder(&t, a), ["a", "d", "f", "c"]);
+    assert!(t.check_invariants().is_ok());
+
+    // The node itself is never tested
+    a.retain_descendants(&mut t, RetainMode::HoistChildren, |_, _| false);
+    assert_eq!(preorder(&t, a), ["a"]);
+}
@@ -0,0 +1,65 @@
+extern crate trees;
+
+use trees::arena::Tree;
+use trees::dot::DotOptions;
+use trees::rctree::RcNode;
+
+#[test]
+fn arena_to_dot() {
+    let mut t: Tree<(), &str> = Tree::new(());
+    let a = t.create_node("a");
+    let b = a.append_child_value(&mut t, "b");
+    a.append_child_value(&mut t, "c \"quoted\"");
+    b.append_child_value(&mut t, "d");
+
+    assert_eq!(a.to_dot(&t, &DotOptions::new()), "digraph \"tree\" {\n    n0;\n    n1;\n    n2;\n    n3;\n    n0 -> n1;\n    n1 -> n2;\n    n0 -> n3;\n}\n");
+
+    let options = DotOptions::new()
+        .name("example")
+        .label(|v: &&str| v.to_string())
+        .attributes(|v| if *v == "b" { vec![("color", "red".to_string())] } else { Vec::new() })
+        .cluster(|v| *v == "b")
+        .sibling_edges(true);
+    let expected = r#"digraph "example" {
+    n0 [label="a"];
+    subgraph cluster_1 {
+        n1 [label="b", color="red"];
+        n2 [label="d"];
+    }
+    n3 [label="c \"quoted\""];
+    n0 -> n1;
+    n1 -> n2;
+    n0 -> n3;
+    n1 -> n3 [style=dashed, constraint=false];
+}
+"#;
+    assert_eq!(a.to_dot(&t, &options), expected);
+}
+
+#[test]
+fn rc_to_dot() {
+    let root = RcNode::new(1);
+    let c = root.append_child_value(2);
+    c.append_child_value(3);
+    c.append_child_value(4);
+
+    let options = DotOptions::new().label(|v: &i32| v.to_string()).cluster(|v| v % 2 == 0).sibling_edges(true);
+    let expected = r#"digraph "tree" {
+    n0 [label="1"];
+    subgraph cluster_1 {
+        n1 [label="2"];
+        n2 [label="3"];
+        subgraph cluster_3 {
+            n3 [label="4"];
+        }
+    }
+    n0 -> n1;
+    n1 -> n2;
+    n1 -> n3;
+    n2 -> n3 [style=dashed, constraint=false];
+}
+"#;
+    assert_eq!(root.to_dot(&options), expected);
+    // Only the subtree is written
+    assert_eq!(c.to_dot(&options).matches("label").count(), 3);
+}
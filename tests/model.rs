@@ -0,0 +1,43 @@
+extern crate trees;
+
+use trees::arena::Tree;
+use trees::testing::{check_ops, Model, Op};
+
+fn sample() -> Model<u32> { Model::new(1, vec![Model::new(2, vec![Model::leaf(4), Model::leaf(5)]), Model::leaf(3)]) }
+
+#[test]
+fn conversions() {
+    let m = sample();
+    assert_eq!(m.len(), 5);
+    assert_eq!(m.values(), [&1, &2, &4, &5, &3]);
+
+    let mut t: Tree<(), u32> = Tree::new(());
+    let root = m.to_arena(&mut t);
+    assert_eq!(Model::from_arena(&t, root), m);
+    assert_eq!(Model::from_rc(&m.to_rc()), m);
+
+    t.push_root(root);
+    t.push_root_value(6);
+    let roots: Vec<_> = t.roots().map(|r| Model::from_arena(&t, r)).collect();
+    assert_eq!(roots, [m, Model::leaf(6)]);
+}
+
+#[test]
+fn ops_match_model() {
+    let ops = vec![
+        Op::AppendChild(1, 6),
+        Op::PrependChild(0, 7),
+        Op::InsertNextSibling(3, 8),
+        Op::InsertPrevSibling(2, 9),
+        // The root has no siblings and cannot be removed
+        Op::InsertNextSibling(0, 10),
+        Op::Remove(9),
+    ];
+    let result = check_ops(&sample(), &ops).unwrap();
+    let expected = Model::new(1, vec![Model::leaf(7), Model::leaf(9), Model::new(2, vec![Model::leaf(4), Model::leaf(8), Model::leaf(5), Model::leaf(6)]), Model::leaf(3)]);
+    assert_eq!(result, expected);
+
+    // Positions wrap around the number of nodes
+    let result = check_ops(&result, &[Op::Remove(8), Op::RemoveChildren(11)]).unwrap();
+    assert_eq!(result, Model::new(1, vec![Model::leaf(7), Model::leaf(9), Model::leaf(2)]));
+}
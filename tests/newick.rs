@@ -0,0 +1,122 @@
+extern crate trees;
+
+use trees::arena::Tree;
+use trees::newick::{parse_arena, parse_rc, write_arena, write_rc, NewickNode, NewickValue};
+use trees::rctree::RcNode;
+
+fn labels(root: &RcNode<NewickNode>) -> Vec<Option<String>> {
+    let mut out = vec![root.value().label.clone()];
+    for c in root.children() {
+        out.extend(labels(&c));
+    }
+    out
+}
+
+#[test]
+fn parse_and_write_arena() {
+    let mut t: Tree<(), NewickNode> = Tree::new(());
+    let roots = parse_arena("(A:0.1,B:0.2,(C,D)E:0.5)F;", &mut t).unwrap();
+    assert_eq!(roots.len(), 1);
+    let root = roots[0];
+    assert_eq!(t.roots().collect::<Vec<_>>(), roots);
+    assert_eq!(t[root].label.as_deref(), Some("F"));
+    let children: Vec<_> = root.children(&t).map(|c| (t[c].label.clone().unwrap(), t[c].branch_length)).collect();
+    assert_eq!(children, [("A".to_string(), Some(0.1)), ("B".to_string(), Some(0.2)), ("E".to_string(), Some(0.5))]);
+    assert_eq!(write_arena(&t, root), "(A:0.1,B:0.2,(C,D)E:0.5)F;");
+}
+
+#[test]
+fn labels_comments_and_whitespace() {
+    let input = "[a comment] ( 'it''s here' : 1e-3 ,\n  under_score [another] , 'a,b'[x] ) ;";
+    let roots = parse_rc::<NewickNode>(input).unwrap();
+    let root = &roots[0];
+    assert_eq!(labels(root), [None, Some("it's here".to_string()), Some("under score".to_string()), Some("a,b".to_string())]);
+    assert_eq!(root.first_child().unwrap().value().branch_length, Some(0.001));
+    // Labels that cannot be written as they are get quoted, so they are read back unchanged
+    assert_eq!(write_rc(root), "('it''s here':0.001,'under score','a,b');");
+    assert_eq!(write_rc(&parse_rc::<NewickNode>(&write_rc(root)).unwrap()[0]), write_rc(root));
+}
+
+#[test]
+fn multiple_trees() {
+    let roots = parse_rc::<NewickNode>("(A,B)C;\n((D,E),F);\n;\n").unwrap();
+    let written: Vec<_> = roots.iter().map(write_rc).collect();
+    assert_eq!(written, ["(A,B)C;", "((D,E),F);", ";"]);
+
+    let mut t: Tree<(), NewickNode> = Tree::new(());
+    parse_arena("(A,B)C; D;", &mut t).unwrap();
+    parse_arena("E;", &mut t).unwrap();
+    assert_eq!(t.roots().map(|r| t[r].label.clone().unwrap()).collect::<Vec<_>>(), ["C", "D", "E"]);
+}
+
+#[test]
+fn custom_values() {
+    #[derive(Debug, PartialEq)]
+    struct Taxon(String, f64);
+    impl NewickValue for Taxon {
+        fn from_newick(label: Option<String>, branch_length: Option<f64>) -> Self { Taxon(label.unwrap_or_default(), branch_length.unwrap_or(0.0)) }
+        fn label(&self) -> Option<&str> { if self.0.is_empty() { None } else { Some(&self.0) } }
+        fn branch_length(&self) -> Option<f64> { Some(self.1) }
+    }
+    let e = parse_rc::<Taxon>("(A:1,B)").unwrap_err();
+    assert_eq!((e.line(), e.column()), (1, 8));
+    let roots = parse_rc::<Taxon>("(A:1.5,B);").unwrap();
+    assert_eq!(*roots[0].last_child().unwrap().value(), Taxon("B".into(), 0.0));
+    assert_eq!(write_rc(&roots[0]), "(A:1.5,B:0):0;");
+}
+
+#[test]
+fn special_branch_lengths() {
+    let root = RcNode::new(NewickNode::default());
+    for &length in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.5] {
+        root.append_child_value(NewickNode::from_newick(None, Some(length)));
+    }
+    let written = write_rc(&root);
+    assert_eq!(written, "(:NaN,:inf,:-inf,:-0.5);");
+    let parsed = &parse_rc::<NewickNode>(&written).unwrap()[0];
+    assert!(parsed.first_child().unwrap().value().branch_length.unwrap().is_nan());
+    assert_eq!(write_rc(parsed), written);
+}
+
+#[test]
+fn failed_parse_leaves_arena_unchanged() {
+    let mut t: Tree<(), NewickNode> = Tree::new(());
+    parse_arena("(A,B)C;", &mut t).unwrap();
+    assert!(parse_arena("(D,E)F; ((G,H),I", &mut t).is_err());
+    assert_eq!(t.all_nodes().count(), 3);
+    assert_eq!(t.detached_roots().count(), 0);
+    assert!(t.check_invariants().is_ok());
+    let roots = parse_arena("J;", &mut t).unwrap();
+    assert_eq!(t.roots().count(), 2);
+    assert_eq!(t[roots[0]].label.as_deref(), Some("J"));
+}
+
+#[test]
+fn parse_errors() {
+    let error = |input: &str| {
+        let e = parse_rc::<NewickNode>(input).unwrap_err();
+        (e.line(), e.column(), e.message().to_string())
+    };
+    assert_eq!(error("(A,B"), (1, 1, "unmatched '('".to_string()));
+    assert_eq!(error("(A,B));"), (1, 6, "unmatched ')'".to_string()));
+    assert_eq!(error("(A,B)C"), (1, 7, "unexpected end of input, expected ';'".to_string()));
+    assert_eq!(error("(A,\n B:x);"), (2, 4, "invalid branch length".to_string()));
+    assert_eq!(error("(A:,B);"), (1, 4, "expected a branch length".to_string()));
+    assert_eq!(error("(A:1.2.3);"), (1, 4, "invalid branch length".to_string()));
+    assert_eq!(error("(A 'B');"), (1, 4, "expected ',' or ')'".to_string()));
+    assert_eq!(error("('A);"), (1, 2, "unterminated quoted label".to_string()));
+    assert_eq!(error("(A)[comment;"), (1, 4, "unterminated comment".to_string()));
+    assert_eq!(
+        parse_rc::<NewickNode>("(A,B").unwrap_err().to_string(),
+        "Invalid Newick input at line 1, column 1: unmatched '('"
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+fn deep_trees() {
+    let depth = 100_000;
+    let input = format!("{}A{};", "(".repeat(depth), ")".repeat(depth));
+    let roots = parse_rc::<NewickNode>(&input).unwrap();
+    assert_eq!(write_rc(&roots[0]), input);
+}
@@ -0,0 +1,90 @@
+extern crate trees;
+
+use trees::arena::Tree;
+use trees::rctree::RcNode;
+use trees::NodePath;
+
+#[test]
+fn display_and_parse() {
+    let p = NodePath::from(vec![0, 3, 1]);
+    assert_eq!(p.to_string(), "/0/3/1");
+    assert_eq!("/0/3/1".parse::<NodePath>(), Ok(p));
+    assert_eq!(NodePath::root().to_string(), "/");
+    assert!("/".parse::<NodePath>().unwrap().is_root());
+
+    assert_eq!("0/1".parse::<NodePath>().unwrap_err().position(), 0);
+    assert_eq!("/0//1".parse::<NodePath>().unwrap_err().position(), 3);
+    assert_eq!("/0/x".parse::<NodePath>().unwrap_err().position(), 3);
+    assert_eq!("/0/+1".parse::<NodePath>().unwrap_err().position(), 3);
+    assert!("/0/".parse::<NodePath>().is_err());
+}
+
+#[test]
+fn document_order() {
+    let mut paths: Vec<NodePath> = ["/1", "/0/2", "/", "/0", "/0/10", "/0/2/0"].iter().map(|s| s.parse().unwrap()).collect();
+    paths.sort();
+    let sorted: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+    assert_eq!(sorted, ["/", "/0", "/0/2", "/0/2/0", "/0/10", "/1"]);
+    assert!(paths[1].is_ancestor_of(&paths[3]));
+    assert!(!paths[3].is_ancestor_of(&paths[3]));
+    assert_eq!(paths[3].parent(), Some(paths[2].clone()));
+}
+
+#[test]
+fn arena_paths() {
+    let mut t: Tree<(), i32> = Tree::new(());
+    let root = t.create_node(0);
+    let a = root.append_child_value(&mut t, 1);
+    let b = root.append_child_value(&mut t, 2);
+    b.append_child_value(&mut t, 3);
+    let d = b.append_child_value(&mut t, 4);
+    assert_eq!(root.path(&t), NodePath::root());
+    assert_eq!(a.path(&t).to_string(), "/0");
+    assert_eq!(d.path(&t).to_string(), "/1/1");
+    assert_eq!(t.resolve(root, &d.path(&t)), Some(d));
+    assert_eq!(t.resolve(root, &"/1/2".parse().unwrap()), None);
+}
+
+#[test]
+fn rc_paths() {
+    let root = RcNode::new(0);
+    root.append_child_value(1);
+    let b = root.append_child_value(2);
+    b.append_child_value(3);
+    let d = b.append_child_value(4);
+    assert_eq!(d.path().to_string(), "/1/1");
+    assert!(root.resolve(&d.path()).unwrap().ptr_eq(&d));
+    assert!(root.resolve(&"/2".parse().unwrap()).is_none());
+}
+
+struct Entry {
+    name: String,
+}
+fn entry(name: &str) -> Entry { Entry { name: name.to_string() } }
+
+#[test]
+fn arena_key_paths() {
+    let mut t: Tree<(), Entry> = Tree::new(());
+    let root = t.create_node(entry(""));
+    let etc = root.append_child_value(&mut t, entry("etc"));
+    let sites = root.get_or_create_path(&mut t, ["etc", "nginx", "sites"], |e: &Entry| e.name.as_str(), entry);
+    assert_eq!(t[sites].name, "sites");
+    assert_eq!(sites.path(&t).to_string(), "/0/0/0");
+    assert_eq!(t.get(etc).children().count(), 1);
+    assert_eq!(root.find_path(&t, ["etc", "nginx", "sites"], |e: &Entry| e.name.as_str()), Some(sites));
+    assert_eq!(root.find_path(&t, vec!["etc".to_string(), "ssh".to_string()], |e: &Entry| e.name.as_str()), None);
+    // Existing nodes are reused
+    let again = root.get_or_create_path(&mut t, ["etc", "nginx", "sites"], |e: &Entry| e.name.as_str(), |_| panic!("should exist"));
+    assert_eq!(again, sites);
+}
+
+#[test]
+fn rc_key_paths() {
+    let root = RcNode::new(entry(""));
+    let sites = root.get_or_create_path(["etc", "nginx", "sites"], |e: &Entry| e.name.as_str(), entry);
+    root.get_or_create_path(["etc", "ssh"], |e: &Entry| e.name.as_str(), entry);
+    assert_eq!(sites.path().to_string(), "/0/0/0");
+    assert!(root.find_path(["etc", "nginx", "sites"], |e: &Entry| e.name.as_str()).unwrap().ptr_eq(&sites));
+    assert_eq!(root.find_path(["etc", "ssh"], |e: &Entry| e.name.as_str()).unwrap().path().to_string(), "/0/1");
+    assert!(root.find_path(["usr"], |e: &Entry| e.name.as_str()).is_none());
+}
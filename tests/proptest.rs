@@ -0,0 +1,27 @@
+#![cfg(feature = "proptest")]
+
+#[macro_use]
+extern crate proptest;
+extern crate trees;
+
+use proptest::prelude::any;
+use trees::testing::strategy::{arena_tree, model, ops, rc_tree};
+use trees::testing::{check_ops, Model};
+
+proptest! {
+    #[test]
+    fn ops_match_model(initial in model(any::<u8>(), 3, 3), ops in ops(any::<u8>(), 30)) {
+        let result = check_ops(&initial, &ops);
+        prop_assert!(result.is_ok(), "{}", result.unwrap_err());
+    }
+
+    #[test]
+    fn generated_trees_are_bounded(arena in arena_tree(any::<u8>(), 2, 3), rc in rc_tree(any::<u8>(), 2, 3)) {
+        let (t, root) = (&arena.tree, arena.root);
+        prop_assert!(t.check_invariants().is_ok());
+        prop_assert_eq!(t.roots().collect::<Vec<_>>(), vec![root]);
+        prop_assert!(root.depth_first_search_subtree(t).all(|(depth, n)| depth <= 2 && n.children(t).count() <= 3));
+        prop_assert!(rc.check_subtree().is_ok());
+        prop_assert!(Model::from_rc(&rc).len() <= 1 + 3 + 9);
+    }
+}
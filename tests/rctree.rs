@@ -0,0 +1,431 @@
+extern crate trees;
+
+use trees::rctree::{RcCellNode, RcMutNode, RcNode, WeakRcNode};
+use trees::{InsertPosition, RetainMode};
+
+const LARGE: usize = 1_000_000;
+
+fn wide_tree() -> RcNode<u32> {
+    let root = RcNode::new(0);
+    for i in 0..LARGE {
+        root.append_child_value(i as u32);
+    }
+    root
+}
+
+fn deep_chain() -> RcNode<u32> {
+    let root = RcNode::new(0);
+    let mut cur = root.clone();
+    for i in 0..LARGE {
+        cur = cur.append_child_value(i as u32);
+    }
+    root
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+#[cfg_attr(miri, ignore)]
+fn drop_wide_tree() {
+    let root = wide_tree();
+    drop(root);
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+#[cfg_attr(miri, ignore)]
+fn drop_deep_chain() {
+    let root = deep_chain();
+    drop(root);
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+#[cfg_attr(miri, ignore)]
+fn drop_deep_chain_from_middle() {
+    let root = deep_chain();
+    let mut mid = root.clone();
+    for _ in 0..(LARGE / 2) {
+        mid = mid.first_child().unwrap();
+    }
+    drop(root);
+    // The parent links are weak, so the upper half is gone while the lower half is kept alive
+    assert!(mid.parent().is_none());
+    assert_eq!(mid.children().count(), 1);
+    drop(mid);
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+#[cfg_attr(miri, ignore)]
+fn debug_wide_tree() {
+    let root = wide_tree();
+    let s = format!("{:?}", root);
+    assert!(s.starts_with("0 [012"));
+    assert!(s.ends_with("999999]"));
+}
+
+#[test]
+#[cfg_attr(feature = "debug-checks", ignore)]
+#[cfg_attr(miri, ignore)]
+fn debug_deep_chain() {
+    let root = deep_chain();
+    let s = format!("{:?}", root);
+    assert!(s.starts_with("0 [0 [1 [2 ["));
+    assert!(s.trim_end_matches(']').ends_with(" [999999"));
+    assert_eq!(s.matches(']').count(), LARGE);
+}
+
+#[test]
+fn debug_subtree_excludes_siblings() {
+    let root = RcNode::new(0);
+    let a = root.append_child_value(1);
+    a.append_child_value(11);
+    root.append_child_value(2);
+    assert_eq!(format!("{:?}", a), "1 [11]");
+    assert_eq!(format!("{:?}", root), "0 [1 [11]2]");
+}
+
+/// Verifies the links of every node underneath the root and returns the structure as a string.
+fn checked(root: &RcNode<u32>) -> String {
+    let mut result = format!("{}", root.value());
+    let children: Vec<_> = root.children().collect();
+    assert_eq!(root.first_child().is_some(), !children.is_empty());
+    if let Some(fc) = root.first_child() {
+        assert!(fc.ptr_eq(&children[0]));
+        assert!(fc.prev_sibling().is_none());
+    }
+    if let Some(lc) = root.last_child() {
+        assert!(lc.ptr_eq(children.last().unwrap()));
+        assert!(lc.next_sibling().is_none());
+    }
+    for (i, c) in children.iter().enumerate() {
+        assert!(c.parent().unwrap().ptr_eq(root));
+        if i > 0 {
+            assert!(c.prev_sibling().unwrap().ptr_eq(&children[i - 1]));
+        }
+    }
+    if !children.is_empty() {
+        let parts: Vec<_> = children.iter().map(checked).collect();
+        result.push('(');
+        result.push_str(&parts.join(","));
+        result.push(')');
+    }
+    result
+}
+
+fn sample() -> (RcNode<u32>, Vec<RcNode<u32>>) {
+    let root = RcNode::new(0);
+    let mut nodes = vec![root.clone()];
+    for i in 1..4 {
+        let c = root.append_child_value(i);
+        nodes.push(c.clone());
+        for j in 1..3 {
+            nodes.push(c.append_child_value(i * 10 + j));
+        }
+    }
+    (root, nodes)
+}
+
+fn find(nodes: &[RcNode<u32>], value: u32) -> RcNode<u32> { nodes.iter().find(|n| *n.value() == value).unwrap().clone() }
+
+#[test]
+fn append_and_prepend() {
+    let root = RcNode::new(0);
+    root.append_child_value(2);
+    root.prepend_child_value(1);
+    root.append_child_value(3);
+    assert_eq!(checked(&root), "0(1,2,3)");
+}
+
+#[test]
+fn insert_siblings() {
+    let (root, nodes) = sample();
+    find(&nodes, 1).insert_next_sibling_value(4);
+    find(&nodes, 1).insert_prev_sibling_value(5);
+    find(&nodes, 3).insert_next_sibling_value(6);
+    assert_eq!(checked(&root), "0(5,1(11,12),4,2(21,22),3(31,32),6)");
+
+    // Inserting a node next to its current neighbour
+    find(&nodes, 11).insert_next_sibling(&find(&nodes, 12));
+    find(&nodes, 22).insert_prev_sibling(&find(&nodes, 21));
+    assert_eq!(checked(&root), "0(5,1(11,12),4,2(21,22),3(31,32),6)");
+}
+
+#[test]
+fn remove_first_middle_and_last() {
+    let (root, nodes) = sample();
+    find(&nodes, 11).remove();
+    find(&nodes, 2).remove();
+    find(&nodes, 32).remove();
+    assert_eq!(checked(&root), "0(1(12),3(31))");
+    assert_eq!(checked(&find(&nodes, 2)), "2(21,22)");
+    assert!(find(&nodes, 2).parent().is_none());
+
+    find(&nodes, 12).remove();
+    find(&nodes, 12).remove();
+    assert_eq!(checked(&root), "0(1,3(31))");
+    root.remove_children();
+    assert_eq!(checked(&root), "0");
+}
+
+#[test]
+fn reparent() {
+    let (root, nodes) = sample();
+    find(&nodes, 3).append_child(&find(&nodes, 1));
+    assert_eq!(checked(&root), "0(2(21,22),3(31,32,1(11,12)))");
+    find(&nodes, 21).prepend_child(&find(&nodes, 32));
+    assert_eq!(checked(&root), "0(2(21(32),22),3(31,1(11,12)))");
+    root.append_child(&find(&nodes, 11));
+    assert_eq!(checked(&root), "0(2(21(32),22),3(31,1(12)),11)");
+
+    let other = RcNode::new(100);
+    other.append_child(&find(&nodes, 2));
+    assert_eq!(checked(&root), "0(3(31,1(12)),11)");
+    assert_eq!(checked(&other), "100(2(21(32),22))");
+}
+
+#[test]
+fn reparent_after_parent_dropped() {
+    let child = {
+        let root = RcNode::new(0);
+        let c = root.append_child_value(1);
+        root.append_child_value(2);
+        c
+    };
+    assert!(child.parent().is_none());
+    let root = RcNode::new(10);
+    root.append_child(&child);
+    assert_eq!(checked(&root), "10(1)");
+}
+
+#[test]
+fn structural_edits() {
+    let (root, nodes) = sample();
+    find(&nodes, 2).unwrap();
+    assert_eq!(checked(&root), "0(1(11,12),21,22,3(31,32))");
+    find(&nodes, 21).wrap_with_value(4);
+    assert_eq!(checked(&root), "0(1(11,12),4(21),22,3(31,32))");
+    find(&nodes, 22).replace_with(&find(&nodes, 11));
+    assert_eq!(checked(&root), "0(1(12),4(21),11,3(31,32))");
+    RcNode::swap(&find(&nodes, 1), &find(&nodes, 32));
+    assert_eq!(checked(&root), "0(32,4(21),11,3(31,1(12)))");
+}
+
+#[test]
+fn structural_edits_on_detached_nodes() {
+    let (root, nodes) = sample();
+    let w = RcNode::new(4);
+    assert!(find(&nodes, 2).wrap_with(&w).ptr_eq(&w));
+    let x = find(&nodes, 31).wrap_with_value(5);
+    assert_eq!(checked(&root), "0(1(11,12),4(2(21,22)),3(5(31),32))");
+
+    // Replacing with a node from elsewhere moves it, wrapping a root leaves the wrapper detached
+    let other = RcNode::new(6);
+    assert!(x.replace_with(&other).ptr_eq(&other));
+    assert_eq!(checked(&root), "0(1(11,12),4(2(21,22)),3(6,32))");
+    let top = root.wrap_with_value(7);
+    assert!(top.parent().is_none());
+    assert_eq!(checked(&top), "7(0(1(11,12),4(2(21,22)),3(6,32)))");
+
+    // Unwrapping a node without a parent leaves its children detached
+    top.unwrap();
+    assert!(root.parent().is_none());
+    assert_eq!(checked(&top), "7");
+    RcNode::swap(&find(&nodes, 1), &x);
+    assert_eq!(checked(&root), "0(5(31),4(2(21,22)),3(6,32))");
+    assert_eq!(checked(&find(&nodes, 1)), "1(11,12)");
+}
+
+#[test]
+fn wrap_with_descendant() {
+    let (root, nodes) = sample();
+    find(&nodes, 1).wrap_with(&find(&nodes, 11));
+    assert_eq!(checked(&root), "0(11(1(12)),2(21,22),3(31,32))");
+}
+
+#[test]
+#[should_panic(expected = "Unable to wrap a node with itself or one of its ancestors")]
+fn wrap_with_ancestor_panics() {
+    let (root, nodes) = sample();
+    find(&nodes, 31).wrap_with(&root);
+}
+
+#[test]
+#[should_panic(expected = "Unable to wrap a node with itself or one of its ancestors")]
+fn wrap_with_itself_panics() {
+    let (_root, nodes) = sample();
+    find(&nodes, 3).wrap_with(&find(&nodes, 3));
+}
+
+#[test]
+#[should_panic(expected = "Unable to replace a node with one of its ancestors")]
+fn replace_with_ancestor_panics() {
+    let (_root, nodes) = sample();
+    find(&nodes, 31).replace_with(&find(&nodes, 3));
+}
+
+#[test]
+fn sibling_ranges() {
+    let (root, nodes) = sample();
+    RcNode::move_sibling_range(&find(&nodes, 1), &find(&nodes, 2), &find(&nodes, 31), InsertPosition::Last);
+    assert_eq!(checked(&root), "0(3(31(1(11,12),2(21,22)),32))");
+    let n = find(&nodes, 1).split_children_after_value(4);
+    root.append_child(&n);
+    assert_eq!(checked(&root), "0(3(31(1(11,12)),32),4(2(21,22)))");
+    let w = RcNode::new(5);
+    assert!(find(&nodes, 31).split_children_after(&w).ptr_eq(&w));
+    assert_eq!(checked(&w), "5(32)");
+}
+
+#[test]
+fn retain() {
+    let (root, nodes) = sample();
+    root.retain_descendants(RetainMode::HoistChildren, |n| *n.value() != 2);
+    assert_eq!(checked(&root), "0(1(11,12),21,22,3(31,32))");
+    root.retain_descendants(RetainMode::RemoveSubtree, |n| *n.value() != 1 && *n.value() != 32);
+    assert_eq!(checked(&root), "0(21,22,3(31))");
+    drop(nodes);
+    assert_eq!(checked(&root), "0(21,22,3(31))");
+}
+
+#[test]
+fn weak_handles() {
+    let (root, nodes) = sample();
+    let weak = find(&nodes, 2).downgrade();
+    drop(nodes);
+    assert_eq!(*weak.upgrade().unwrap().value(), 2);
+    weak.upgrade().unwrap().remove();
+    assert!(weak.upgrade().is_none());
+    assert_eq!(checked(&root), "0(1(11,12),3(31,32))");
+}
+
+#[test]
+fn weak_handle_counts() {
+    let root = RcNode::new(0);
+    let a = RcNode::new(1);
+    let weak = a.downgrade();
+    assert_eq!((weak.strong_count(), weak.weak_count()), (1, 1));
+    let weak2 = weak.clone();
+    assert_eq!((a.strong_count(), a.weak_count()), (1, 2));
+
+    // The parent holds two strong links (first and last child), the next sibling a weak one
+    root.append_child(&a);
+    assert_eq!(weak.strong_count(), 3);
+    root.append_child_value(2);
+    assert_eq!((weak.strong_count(), weak.weak_count()), (2, 3));
+
+    a.remove();
+    drop(root);
+    assert_eq!((weak.strong_count(), weak.weak_count()), (1, 2));
+    drop(a);
+    assert_eq!((weak2.strong_count(), weak2.weak_count()), (0, 0));
+    assert!(weak2.upgrade().is_none());
+}
+
+#[test]
+fn weak_handle_identity() {
+    use std::collections::HashSet;
+
+    let (_root, nodes) = sample();
+    let mut set = HashSet::new();
+    assert!(set.insert(nodes[1].downgrade()));
+    assert!(!set.insert(nodes[1].downgrade()));
+    assert!(set.insert(nodes[2].downgrade()));
+    assert!(set.contains(&find(&nodes, 1).downgrade()));
+    assert!(!set.contains(&find(&nodes, 3).downgrade()));
+
+    // Equality is by identity, not by value
+    let twin = RcNode::new(1);
+    assert_ne!(twin.downgrade(), nodes[1].downgrade());
+    assert!(twin.downgrade().is_node(&twin) && !twin.downgrade().is_node(&nodes[1]));
+    assert_eq!(WeakRcNode::<u32>::new(), WeakRcNode::default());
+}
+
+#[test]
+fn mutable_values() {
+    let root = RcMutNode::new_mut(String::from("root"));
+    let child = RcNode::new_mut(String::from("a"));
+    root.append_child(&child);
+    child.borrow_value_mut().push('b');
+    assert_eq!(*root.first_child().unwrap().borrow_value(), "ab");
+    {
+        let _r1 = child.borrow_value();
+        let _r2 = child.borrow_value();
+        assert!(child.try_borrow_value_mut().is_err());
+    }
+    assert_eq!(child.replace_value(String::from("c")), "ab");
+    child.set_value(String::from("d"));
+    let len = child.update_value(|v| {
+        v.push('e');
+        v.len()
+    });
+    assert_eq!(len, 2);
+    assert_eq!(*child.borrow_value(), "de");
+    {
+        let _w = child.borrow_value_mut();
+        assert!(child.try_borrow_value().is_err());
+    }
+}
+
+#[test]
+#[should_panic]
+fn second_mutable_borrow_panics() {
+    let node = RcMutNode::new_mut(1);
+    let _first = node.borrow_value_mut();
+    let _second = node.borrow_value_mut();
+}
+
+#[test]
+fn cell_values() {
+    let root = RcCellNode::new_cell(1);
+    let child = root.append_child_value(std::cell::Cell::new(2));
+    assert_eq!(child.get_value(), 2);
+    assert_eq!(child.replace_value(3), 2);
+    child.set_value(4);
+    assert_eq!(child.update_value(|v| v * 10), 40);
+    assert_eq!(root.first_child().unwrap().get_value(), 40);
+    assert_eq!(root.get_value(), 1);
+}
+
+#[test]
+fn document_order() {
+    let (_root, nodes) = sample();
+    for (i, a) in nodes.iter().enumerate() {
+        for (j, b) in nodes.iter().enumerate() {
+            assert_eq!(RcNode::compare_document_order(a, b), i.cmp(&j));
+        }
+    }
+    let other = RcNode::new(100);
+    let order = RcNode::compare_document_order(&nodes[3], &other);
+    assert_ne!(order, std::cmp::Ordering::Equal);
+    assert_eq!(RcNode::compare_document_order(&other, &nodes[5]), order.reverse());
+}
+
+#[test]
+fn check_subtree() {
+    let root = RcNode::new(0);
+    for i in 1..5 {
+        root.append_child_value(i).append_child_value(i * 10);
+    }
+    let first = root.first_child().unwrap();
+    first.wrap_with_value(5);
+    root.last_child().unwrap().unwrap();
+    RcNode::swap(&first, &root.last_child().unwrap());
+    let report = root.check_subtree();
+    assert!(report.is_ok());
+    assert_eq!(report.checked(), 9);
+}
+
+#[test]
+#[cfg(not(feature = "debug-checks"))]
+fn check_subtree_violations() {
+    // Appending an ancestor is not checked, so it creates a loop
+    let a = RcNode::new(0);
+    let b = a.append_child_value(1);
+    b.append_child(&a);
+    let kinds: Vec<_> = a.check_subtree().violations().iter().map(|v| v.kind()).collect();
+    assert_eq!(kinds, [trees::ViolationKind::Cycle]);
+    // Break the loop again, so the nodes are freed
+    a.remove();
+    assert!(a.check_subtree().is_ok());
+}
@@ -0,0 +1,122 @@
+extern crate trees;
+
+use trees::arena::rewrite::{Pattern, Rewriter, Rule, Strategy};
+use trees::arena::{Node, Tree};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expr {
+    Num(i64),
+    Var(char),
+    Add,
+    Mul,
+}
+
+fn is_num(e: &Expr) -> bool { matches!(*e, Expr::Num(_)) }
+fn num(t: &Tree<(), Expr>, n: Node<()>) -> i64 {
+    match t[n] {
+        Expr::Num(v) => v,
+        _ => unreachable!(),
+    }
+}
+
+fn to_string(t: &Tree<(), Expr>, n: Node<()>) -> String {
+    match t[n] {
+        Expr::Num(v) => v.to_string(),
+        Expr::Var(c) => c.to_string(),
+        op => {
+            let args: Vec<String> = n.children(t).map(|c| to_string(t, c)).collect();
+            format!("({} {})", if op == Expr::Add { "+" } else { "*" }, args.join(" "))
+        }
+    }
+}
+
+/// Builds `(* (+ 1 2) (+ x 0))`
+fn sample(t: &mut Tree<(), Expr>) -> Node<()> {
+    let mul = t.create_node(Expr::Mul);
+    let a = mul.append_child_value(t, Expr::Add);
+    a.append_child_value(t, Expr::Num(1));
+    a.append_child_value(t, Expr::Num(2));
+    let b = mul.append_child_value(t, Expr::Add);
+    b.append_child_value(t, Expr::Var('x'));
+    b.append_child_value(t, Expr::Num(0));
+    mul
+}
+
+fn simplifier() -> Rewriter<(), Expr> {
+    Rewriter::new()
+        .rule(Rule::new(
+            "fold-add",
+            Pattern::node(|e| *e == Expr::Add).with_children(vec![Pattern::node(is_num).bind("a"), Pattern::node(is_num).bind("b")]),
+            |t, c| {
+                let sum = num(t, c["a"]) + num(t, c["b"]);
+                Some(t.create_node(Expr::Num(sum)))
+            },
+        ))
+        .rule(Rule::new(
+            "add-zero",
+            Pattern::node(|e| *e == Expr::Add).with_children(vec![Pattern::capture("x"), Pattern::node(|e| *e == Expr::Num(0))]),
+            |_, c| Some(c["x"]),
+        ))
+}
+
+#[test]
+fn bottom_up_simplify() {
+    let mut t = Tree::new(());
+    let root = sample(&mut t);
+    let result = simplifier().rewrite(&mut t, root).unwrap();
+    assert_eq!(result.root(), root);
+    assert_eq!(to_string(&t, root), "(* 3 x)");
+    let rules: Vec<&str> = result.trace().iter().map(|s| s.rule()).collect();
+    assert_eq!(rules, ["fold-add", "add-zero"]);
+    assert_eq!(t[result.trace()[0].replacement()], Expr::Num(3));
+}
+
+#[test]
+fn top_down_reaches_fixpoint_across_passes() {
+    // (+ (+ 1 2) 3) needs a second pass when rewriting top-down
+    let mut t = Tree::new(());
+    let root = t.create_node(Expr::Add);
+    let inner = root.append_child_value(&mut t, Expr::Add);
+    inner.append_child_value(&mut t, Expr::Num(1));
+    inner.append_child_value(&mut t, Expr::Num(2));
+    root.append_child_value(&mut t, Expr::Num(3));
+
+    let result = simplifier().strategy(Strategy::TopDown).rewrite(&mut t, root).unwrap();
+    assert_eq!(t[result.root()], Expr::Num(6));
+    assert_eq!(result.trace().len(), 2);
+    assert_eq!(result.trace()[1].node(), root);
+}
+
+#[test]
+fn declined_and_in_place_rules() {
+    let mut t = Tree::new(());
+    let root = sample(&mut t);
+    // Swap the operands of multiplications whose first operand starts with a number (in place)
+    let rewriter = Rewriter::new().rule(Rule::new("swap", Pattern::node(|e| *e == Expr::Mul).bind("m"), |t, c| {
+        let m = c["m"];
+        let first = m.first_child(t).unwrap();
+        if !first.first_child(t).is_some_and(|c| is_num(&t[c])) {
+            return None;
+        }
+        let last = m.last_child(t).unwrap();
+        Node::swap(t, first, last);
+        Some(m)
+    }));
+    let result = rewriter.rewrite(&mut t, root).unwrap();
+    assert_eq!(result.trace().len(), 1);
+    assert_eq!(to_string(&t, root), "(* (+ x 0) (+ 1 2))");
+}
+
+#[test]
+fn step_limit() {
+    let mut t = Tree::new(());
+    let root = t.create_node(Expr::Num(0));
+    let rewriter = Rewriter::new().max_steps(5).rule(Rule::new("inc", Pattern::node(is_num), |t, c| {
+        let v = num(t, c.matched());
+        Some(t.create_node(Expr::Num(v + 1)))
+    }));
+    let err = rewriter.rewrite(&mut t, root).unwrap_err();
+    assert_eq!(err.partial().trace().len(), 6);
+    assert_eq!(t[err.partial().root()], Expr::Num(6));
+    assert_eq!(err.to_string(), "Rewriting did not reach a fixpoint within 5 steps");
+}
@@ -0,0 +1,125 @@
+extern crate trees;
+
+use trees::arena::Tree;
+use trees::rctree::RcNode;
+use trees::select::{Matcher, Selector};
+
+struct Element {
+    name: &'static str,
+    attrs: Vec<(&'static str, &'static str)>,
+}
+impl Matcher for Element {
+    fn matches_name(&self, name: &str) -> bool { self.name == name }
+    fn matches_attribute(&self, name: &str, value: Option<&str>) -> bool { self.attrs.iter().any(|&(n, v)| n == name && value.is_none_or(|value| value == v)) }
+}
+fn el(name: &'static str) -> Element { Element { name, attrs: Vec::new() } }
+fn el_id(name: &'static str, id: &'static str) -> Element {
+    Element {
+        name,
+        attrs: vec![("id", id)],
+    }
+}
+
+/// Builds
+///
+/// ```text
+/// body
+///   div#a
+///     p#p1
+///     p#p2
+///       span#s1
+///     img#i1
+///   div#b
+///     p#p3
+///   p#p4
+/// ```
+fn rc_doc() -> RcNode<Element> {
+    let body = RcNode::new(el("body"));
+    let a = body.append_child_value(el_id("div", "a"));
+    a.append_child_value(el_id("p", "p1"));
+    a.append_child_value(el_id("p", "p2")).append_child_value(el_id("span", "s1"));
+    a.append_child_value(el_id("img", "i1"));
+    let b = body.append_child_value(el_id("div", "b"));
+    b.append_child_value(el_id("p", "p3"));
+    body.append_child_value(el_id("p", "p4"));
+    body
+}
+
+fn ids(nodes: &[RcNode<Element>]) -> Vec<&'static str> { nodes.iter().map(|n| n.value().attrs.first().map_or(n.value().name, |a| a.1)).collect() }
+
+fn select(root: &RcNode<Element>, s: &str) -> Vec<&'static str> { ids(&Selector::parse(s).unwrap().select(root)) }
+
+#[test]
+fn combinators() {
+    let doc = rc_doc();
+    assert_eq!(select(&doc, "*").len(), 9);
+    assert_eq!(select(&doc, "body"), ["body"]);
+    assert_eq!(select(&doc, "p"), ["p1", "p2", "p3", "p4"]);
+    assert_eq!(select(&doc, "div p"), ["p1", "p2", "p3"]);
+    assert_eq!(select(&doc, "body > p"), ["p4"]);
+    assert_eq!(select(&doc, "body span"), ["s1"]);
+    assert_eq!(select(&doc, "p + img"), ["i1"]);
+    assert_eq!(select(&doc, "div ~ p"), ["p4"]);
+    assert_eq!(select(&doc, "img, span"), ["s1", "i1"]);
+    assert_eq!(select(&doc, "[id=b] > p, [id='p1']"), ["p1", "p3"]);
+}
+
+#[test]
+fn pseudo_classes() {
+    let doc = rc_doc();
+    assert_eq!(select(&doc, "p:first-child"), ["p1", "p3"]);
+    assert_eq!(select(&doc, "div > :last-child"), ["i1", "p3"]);
+    assert_eq!(select(&doc, ":only-child"), ["body", "s1", "p3"]);
+    assert_eq!(select(&doc, "div > :nth-child(2n)"), ["p2"]);
+    assert_eq!(select(&doc, "div > :nth-child(odd)"), ["p1", "i1", "p3"]);
+    assert_eq!(select(&doc, "div > :nth-child( -n + 2 )"), ["p1", "p2", "p3"]);
+    assert_eq!(select(&doc, "body > :nth-last-child(1)"), ["p4"]);
+    assert_eq!(select(&doc, "div:has(img)"), ["a"]);
+    assert_eq!(select(&doc, ":has(> span)"), ["p2"]);
+    assert_eq!(select(&doc, "div:has(+ p)"), ["b"]);
+    assert_eq!(select(&doc, "p:not(:first-child, [id=p4])"), ["p2"]);
+}
+
+#[test]
+fn nth_positions() {
+    let list = RcNode::new(el("ul"));
+    for _ in 0..300 {
+        let item = list.append_child_value(el("li"));
+        item.append_child_value(el_id("span", "first"));
+        item.append_child_value(el_id("span", "second"));
+    }
+    assert_eq!(select(&list, "li:nth-child(3n+1) > :nth-last-child(2)").len(), 100);
+    assert_eq!(select(&list, "li:nth-last-child(-n+3) :nth-child(2)"), ["second"; 3]);
+    // Positions that would overflow do not match
+    assert!(select(&list, "li:nth-child(-n-9223372036854775808)").is_empty());
+    assert_eq!(select(&list, "li:nth-child(9223372036854775807n+1)").len(), 1);
+}
+
+#[test]
+fn arena_select() {
+    let mut t: Tree<(), Element> = Tree::new(());
+    let root = t.create_node(el("ul"));
+    for id in ["x", "y", "z"].iter() {
+        root.append_child_value(&mut t, el_id("li", id));
+    }
+    let sel: Selector = "ul > li:nth-child(n+2)".parse().unwrap();
+    let found: Vec<&str> = sel.select(&t.get(root)).iter().map(|n| n.value().attrs[0].1).collect();
+    assert_eq!(found, ["y", "z"]);
+    // The search is limited to the subtree of the starting node
+    let y = t.get(root).first_child().unwrap().next_sibling().unwrap();
+    assert_eq!(Selector::parse("li").unwrap().select(&y).len(), 1);
+    assert!(sel.matches(&y));
+    assert!(Selector::parse("li:first-child").unwrap().select_first(&t.get(root)).is_some());
+}
+
+#[test]
+fn parse_errors() {
+    let err = |s: &str| Selector::parse(s).unwrap_err().position();
+    assert_eq!(err(""), 0);
+    assert_eq!(err("div >"), 5);
+    assert_eq!(err("div[id"), 6);
+    assert_eq!(err("p:nth-child(2x)"), 12);
+    assert_eq!(err("p:hover"), 2);
+    assert_eq!(err("p:not(a"), 7);
+    assert_eq!(err("a)"), 1);
+}
@@ -0,0 +1,142 @@
+extern crate trees;
+
+use std::collections::HashSet;
+use std::thread;
+use trees::sync::ArcNode;
+
+#[test]
+fn concurrent_edits_keep_links_consistent() {
+    let root = ArcNode::new(0u32);
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let root = root.clone();
+            thread::spawn(move || {
+                for i in 0..500 {
+                    let c = root.append_child_value(t * 1000 + i);
+                    if i % 3 == 0 {
+                        c.remove();
+                    } else if i % 5 == 0 {
+                        c.insert_prev_sibling_value(1);
+                    }
+                }
+            })
+        })
+        .collect();
+    let reader = {
+        let root = root.clone();
+        thread::spawn(move || (0..50).map(|_| root.children().count()).max().unwrap())
+    };
+    for w in writers {
+        w.join().unwrap();
+    }
+    let per_thread = (0..500).map(|i| if i % 3 == 0 { 0 } else if i % 5 == 0 { 2 } else { 1 }).sum::<usize>();
+    assert!(reader.join().unwrap() <= 4 * per_thread);
+
+    let children: Vec<_> = root.children().collect();
+    assert_eq!(children.len(), 4 * per_thread);
+    for (i, c) in children.iter().enumerate() {
+        assert!(c.parent().unwrap().ptr_eq(&root));
+        if i > 0 {
+            assert!(c.prev_sibling().unwrap().ptr_eq(&children[i - 1]));
+        }
+    }
+    assert!(root.first_child().unwrap().ptr_eq(&children[0]));
+    assert!(root.last_child().unwrap().ptr_eq(children.last().unwrap()));
+}
+
+#[test]
+fn readers_never_see_partial_edits() {
+    const CHILDREN: u32 = 20;
+    let root = ArcNode::new(0u32);
+    for i in 0..CHILDREN {
+        root.append_child_value(i);
+    }
+    // The writers only reorder the children, so every consistent view contains all of them
+    let debug_len = format!("{:?}", root).len();
+    let writers: Vec<_> = (0..3)
+        .map(|t| {
+            let root = root.clone();
+            thread::spawn(move || {
+                for i in 0..2000 {
+                    let c = root.children().nth((i * 7 + t) % CHILDREN as usize).unwrap();
+                    match i % 3 {
+                        0 => root.prepend_child(&c),
+                        1 => root.append_child(&c),
+                        _ => root.first_child().unwrap().insert_next_sibling(&c),
+                    }
+                }
+            })
+        })
+        .collect();
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let root = root.clone();
+            thread::spawn(move || {
+                for _ in 0..2000 {
+                    let values: HashSet<_> = root.children().map(|c| *c.value()).collect();
+                    assert_eq!(values.len(), CHILDREN as usize);
+                    assert_eq!(format!("{:?}", root).len(), debug_len);
+                }
+            })
+        })
+        .collect();
+    for h in writers.into_iter().chain(readers) {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn moving_nodes_between_trees() {
+    let trees = [ArcNode::new(0u32), ArcNode::new(1)];
+    for i in 0..10 {
+        trees[0].append_child_value(10 + i).append_child_value(100 + i);
+    }
+    let writers: Vec<_> = (0..2)
+        .map(|t| {
+            let trees = trees.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    // Move a child (along with its own child) from one tree to the other
+                    let (from, to) = (&trees[t], &trees[1 - t]);
+                    if let Some(c) = from.first_child() {
+                        to.append_child(&c);
+                    }
+                }
+            })
+        })
+        .collect();
+    let reader = {
+        let trees = trees.clone();
+        thread::spawn(move || {
+            for i in 0..2000 {
+                for c in trees[i % 2].children() {
+                    let grandchildren: Vec<_> = c.children().collect();
+                    assert_eq!(grandchildren.len(), 1);
+                    assert_eq!(*grandchildren[0].value(), *c.value() + 90);
+                }
+            }
+        })
+    };
+    for h in writers.into_iter().chain(Some(reader)) {
+        h.join().unwrap();
+    }
+    let mut values: Vec<_> = trees.iter().flat_map(|r| r.children()).map(|c| *c.value()).collect();
+    values.sort();
+    assert_eq!(values, (10..20).collect::<Vec<_>>());
+    for r in &trees {
+        for c in r.children() {
+            assert!(c.parent().unwrap().ptr_eq(r));
+        }
+    }
+}
+
+#[test]
+fn weak_handles_across_threads() {
+    let root = ArcNode::new(String::from("root"));
+    let child = root.append_child_value(String::from("child"));
+    let weak = child.downgrade();
+    drop(child);
+    let handle = thread::spawn(move || weak.upgrade().map(|n| n.value().clone()));
+    assert_eq!(handle.join().unwrap(), Some(String::from("child")));
+    assert_eq!(format!("{:?}", root), "\"root\" [\"child\"]");
+}